@@ -1,3 +1,5 @@
-# some bids
+# some bids. Bidders pay for the storage of their bids, so they need a storage deposit first
+near call $c storage_deposit --deposit 0.1 --accountId bob.evin.testnet
+near call $c storage_deposit --deposit 0.1 --accountId evin.testnet
 near call $c add_bid '{"nft_contract_id":"royalties.evin.testnet", "token_id":"'$token_id'"}' --accountId bob.evin.testnet --deposit 4.1
 near call $c add_bid '{"nft_contract_id":"royalties.evin.testnet", "token_id":"'$token_id'"}' --accountId evin.testnet --deposit 4.44
\ No newline at end of file
@@ -0,0 +1,202 @@
+use crate::*;
+use crate::internal::hash_auction_id;
+use near_sdk::log;
+
+//bid counts for an auction as returned by the views
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionStats {
+    pub auction_seq: U64, //which of the token's auctions this is, for looking up its bids with get_bids
+    pub bid_count: U64,
+    pub unique_bidder_count: U64,
+    pub min_bid_increment: U128, //amount the next bid has to beat the current bid (or the starting price) by
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+    //returns paginated bids placed on an auction of a given unique sale ID (contract + DELIMITER + token ID), oldest first.
+    //The history is kept after the auction is over. Without an auction_seq this is the token's latest auction
+    pub fn get_bids(
+        &self,
+        nft_contract_token: ContractAndTokenId,
+        auction_seq: Option<U64>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Bid> {
+        let auction_seq = auction_seq.map(|x| x.0).unwrap_or_else(|| self.auction_counts.get(&nft_contract_token).unwrap_or(0));
+
+        //get the bid history for the auction. If there isn't one, return an empty vector
+        let bids = if let Some(bids) = self.bid_history.get(&(nft_contract_token, auction_seq)) {
+            bids
+        } else {
+            return vec![];
+        };
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        bids.iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+
+    //returns how many auctions have been held for a given unique sale ID. Their auction_seqs go from 1 up to this
+    pub fn get_auction_count(&self, nft_contract_token: ContractAndTokenId) -> U64 {
+        U64(self.auction_counts.get(&nft_contract_token).unwrap_or(0))
+    }
+
+    //returns how many bids have been placed on an auction and by how many different accounts, and the increment in effect
+    pub fn get_auction_stats(&self, nft_contract_token: ContractAndTokenId) -> Option<AuctionStats> {
+        self.sales
+            .get(&nft_contract_token)
+            .filter(|sale| sale.is_auction)
+            .map(|sale| {
+                //the increment applies to the current bid, or to the starting price if there are no bids yet
+                let current_price = match sale.bids.as_ref().and_then(|bids| bids.last()) {
+                    Some(current_bid) => current_bid.price.0,
+                    None => sale.auction_condition().1,
+                };
+                let auction_id = (nft_contract_token, sale.auction_seq);
+
+                AuctionStats {
+                    auction_seq: U64(sale.auction_seq),
+                    bid_count: U64(self.bid_history.get(&auction_id).map(|bids| bids.len()).unwrap_or(0)),
+                    unique_bidder_count: U64(self.bidders.get(&auction_id).map(|bidders| bidders.len()).unwrap_or(0)),
+                    min_bid_increment: U128(sale.min_bid_increment_on(current_price)),
+                }
+            })
+    }
+}
+
+impl Contract {
+    //internal method for getting the sequence number of a new auction for the given unique sale ID
+    pub(crate) fn internal_next_auction_seq(&mut self, contract_and_token_id: &ContractAndTokenId) -> u64 {
+        let auction_seq = self.auction_counts.get(contract_and_token_id).unwrap_or(0) + 1;
+        self.auction_counts.insert(contract_and_token_id, &auction_seq);
+        auction_seq
+    }
+
+    //internal method for adding a bid to an auction's history and its set of bidders
+    pub(crate) fn internal_record_bid(&mut self, contract_and_token_id: &ContractAndTokenId, auction_seq: u64, bid: &Bid) {
+        let auction_id = (contract_and_token_id.clone(), auction_seq);
+
+        //get the bid history for the auction. If there is none, we create a new empty vector
+        let mut bids = self.bid_history.get(&auction_id).unwrap_or_else(|| {
+            Vector::new(
+                StorageKey::BidHistoryInner {
+                    //we get a new unique prefix for the collection by hashing the sale ID and auction sequence number
+                    auction_id_hash: hash_auction_id(contract_and_token_id, auction_seq),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        bids.push(bid);
+        self.bid_history.insert(&auction_id, &bids);
+
+        //get the bidders for the auction. If there are none, we create a new empty set
+        let mut bidders = self.bidders.get(&auction_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::BiddersInner {
+                    //we get a new unique prefix for the collection by hashing the sale ID and auction sequence number
+                    auction_id_hash: hash_auction_id(contract_and_token_id, auction_seq),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        bidders.insert(&bid.bidder_id);
+        self.bidders.insert(&auction_id, &bidders);
+
+        log!(
+            "Bid of {} by {} on {} at {}",
+            bid.price.0,
+            bid.bidder_id,
+            contract_and_token_id,
+            bid.timestamp.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    fn auction() -> Contract {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(100, json!({})), NOW);
+        contract
+    }
+
+    fn sale_id() -> ContractAndTokenId {
+        format!("{}{}{}", nft(), DELIMETER, "1")
+    }
+
+    #[test]
+    fn every_bid_is_kept_oldest_first() {
+        let mut contract = auction();
+        bid(&mut contract, bob(), ONE_NEAR, NOW + 1);
+        bid(&mut contract, carol(), 2 * ONE_NEAR, NOW + 2);
+        bid(&mut contract, bob(), 3 * ONE_NEAR, NOW + 3);
+
+        let bids = contract.get_bids(sale_id(), None, None, Some(10));
+        let bids: Vec<(AccountId, u128, u64)> = bids.into_iter().map(|bid| (bid.bidder_id, bid.price.0, bid.timestamp.0)).collect();
+        assert_eq!(bids, vec![(bob(), ONE_NEAR, NOW + 1), (carol(), 2 * ONE_NEAR, NOW + 2), (bob(), 3 * ONE_NEAR, NOW + 3)]);
+
+        let page = contract.get_bids(sale_id(), None, Some(U128(1)), Some(1));
+        assert_eq!(page[0].bidder_id, carol());
+    }
+
+    #[test]
+    fn stats_count_bids_and_unique_bidders() {
+        let mut contract = auction();
+        bid(&mut contract, bob(), ONE_NEAR, NOW + 1);
+        bid(&mut contract, carol(), 2 * ONE_NEAR, NOW + 2);
+        bid(&mut contract, bob(), 3 * ONE_NEAR, NOW + 3);
+
+        let stats = contract.get_auction_stats(sale_id()).unwrap();
+        assert_eq!(stats.bid_count, U64(3));
+        assert_eq!(stats.unique_bidder_count, U64(2));
+    }
+
+    #[test]
+    fn the_history_of_earlier_auctions_is_kept() {
+        let mut contract = auction();
+        bid(&mut contract, bob(), ONE_NEAR, NOW + 1);
+        call(alice(), 1, NOW + 2);
+        contract.remove_sale(nft(), "1".to_string());
+
+        let mut msg = auction_msg(100, json!({}));
+        msg["start_time"] = json!((NOW + 3).to_string());
+        list(&mut contract, alice(), "1", msg, NOW + 2);
+        bid(&mut contract, carol(), 2 * ONE_NEAR, NOW + 4);
+
+        assert_eq!(contract.get_auction_count(sale_id()), U64(2));
+        assert_eq!(contract.get_auction_stats(sale_id()).unwrap().auction_seq, U64(2));
+        assert_eq!(contract.get_bids(sale_id(), Some(U64(1)), None, Some(10))[0].bidder_id, bob());
+        assert_eq!(contract.get_bids(sale_id(), None, None, Some(10))[0].bidder_id, carol());
+    }
+
+    #[test]
+    #[should_panic(expected = "Please use storage_deposit")]
+    fn bidders_pay_for_the_history_out_of_their_storage_deposit() {
+        let mut contract = auction();
+
+        call(bob(), ONE_NEAR, NOW + 1);
+        contract.add_bid(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn fixed_price_sales_have_no_stats() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "100" }, "is_auction": false }), NOW);
+
+        assert!(contract.get_auction_stats(sale_id()).is_none());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::*;
+use crate::internal::{hash_account_id, near_token_id, pay_account};
+use crate::offer_books::{MAX_OFFERS_SCANNED, assert_valid_expiry, insert_into_account_set, insert_into_book, is_expired, remove_from_account_set, remove_from_book};
+
+//struct for a standing order to buy any token from a collection at or below a price. The price is escrowed by the market
+//and the order is filled as soon as a fixed price sale for the collection is listed at or below it
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuyOrder {
+    pub order_id: u64,
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub price: U128, //most the buyer pays, in yoctoNEAR
+    pub expires_at: Option<U64>, //Unix timestamp (in nanoseconds) the order can't be filled after
+}
+
+impl BuyOrder {
+    //whether the order is past its expiry
+    pub(crate) fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //places an order to buy any token from the given collection that is listed at or below the attached deposit, which
+    //the market holds until the order is filled or cancelled. Like a sale, every open order takes up one sale worth of storage deposit
+    #[payable]
+    pub fn place_buy_order(&mut self, nft_contract_id: AccountId, expires_at: Option<U64>) -> u64 {
+        let price = env::attached_deposit();
+        assert!(price > 0, "Attached deposit must be greater than 0");
+
+        assert_valid_expiry(expires_at);
+
+        //make sure the buyer has paid for the storage of one more order
+        let buyer_id = env::predecessor_account_id();
+        self.internal_assert_offer_storage(&buyer_id);
+
+        let order_id = self.next_offer_id;
+        self.next_offer_id += 1;
+
+        let order = BuyOrder {
+            order_id,
+            buyer_id: buyer_id.clone(),
+            nft_contract_id: nft_contract_id.clone(),
+            price: U128(price),
+            expires_at,
+        };
+        self.buy_orders.insert(&order_id, &order);
+
+        //add the order to the collection's order book and to the buyer's orders. We get new unique prefixes by hashing the accounts
+        insert_into_book(
+            &mut self.buy_order_book,
+            &nft_contract_id,
+            StorageKey::BuyOrderBookInner { account_id_hash: hash_account_id(&nft_contract_id) },
+            price,
+            order_id,
+        );
+        insert_into_account_set(
+            &mut self.buy_orders_by_buyer_id,
+            &buyer_id,
+            StorageKey::BuyOrdersByBuyerIdInner { account_id_hash: hash_account_id(&buyer_id) },
+            order_id,
+        );
+
+        order_id
+    }
+
+    //cancels a buy order and refunds it. The buyer can cancel at any time and anyone can remove an expired order
+    #[payable]
+    pub fn cancel_buy_order(&mut self, order_id: u64) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+        let order = self.buy_orders.get(&order_id).expect("No buy order");
+
+        if env::predecessor_account_id() != order.buyer_id {
+            assert!(order.is_expired(), "Only the buyer can cancel an order before it expires");
+        }
+
+        let order = self.internal_remove_buy_order(order_id);
+        pay_account(&near_token_id(), order.buyer_id, order.price.0);
+    }
+
+    /// views
+    //returns paginated buy orders for the given collection, highest price first. Ties go to the oldest order
+    pub fn get_buy_orders(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<BuyOrder> {
+        let order_book = match self.buy_order_book.get(&nft_contract_id) {
+            Some(order_book) => order_book,
+            None => return vec![],
+        };
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        order_book
+            .iter_rev()
+            .skip(start as usize)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .map(|(_, order_id)| self.buy_orders.get(&order_id).unwrap())
+            .collect()
+    }
+
+    //returns every buy order placed by the given buyer
+    pub fn get_buy_orders_by_buyer_id(&self, buyer_id: AccountId) -> Vec<BuyOrder> {
+        self.buy_orders_by_buyer_id
+            .get(&buyer_id)
+            .map(|by_buyer_id| {
+                by_buyer_id
+                    .iter()
+                    .map(|order_id| self.buy_orders.get(&order_id).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    //internal method for removing a buy order from the market. The escrowed price is not refunded here
+    pub(crate) fn internal_remove_buy_order(&mut self, order_id: u64) -> BuyOrder {
+        let order = self.buy_orders.remove(&order_id).expect("No buy order");
+
+        remove_from_book(&mut self.buy_order_book, &order.nft_contract_id, order.price.0, order_id);
+        remove_from_account_set(&mut self.buy_orders_by_buyer_id, &order.buyer_id, order_id);
+
+        order
+    }
+
+    /*
+        internal method for filling the best buy order for a sale that was just listed. Only fixed price sales priced in NEAR
+        that can be bought right away are matched. The buyer pays the listing price and the rest of their order is refunded.
+        Expired orders found along the way are removed and refunded. Only the first MAX_OFFERS_SCANNED orders are looked at
+    */
+    pub(crate) fn internal_fill_buy_order(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+
+        if sale.is_auction || sale.is_dutch_auction || sale.is_expired_listing() {
+            return;
+        }
+        if sale.start_time.map(|start_time| start_time > env::block_timestamp()).unwrap_or(false) {
+            return;
+        }
+        let price = match sale.sale_conditions.get(&near_token_id()) {
+            Some(price) => price.0,
+            None => return,
+        };
+        let order_book = match self.buy_order_book.get(&nft_contract_id) {
+            Some(order_book) => order_book,
+            None => return,
+        };
+
+        //walk the orders from the highest price down until they no longer cover the listing price
+        let mut expired_order_ids = Vec::new();
+        let mut matched_order = None;
+        for (_, order_id) in order_book.iter_rev().take(MAX_OFFERS_SCANNED) {
+            let order = self.buy_orders.get(&order_id).unwrap();
+            if order.price.0 < price {
+                break;
+            }
+            if order.is_expired() {
+                expired_order_ids.push(order_id);
+            } else if order.buyer_id != sale.owner_id {
+                matched_order = Some(order);
+                break;
+            }
+        }
+
+        for order_id in expired_order_ids {
+            let order = self.internal_remove_buy_order(order_id);
+            pay_account(&near_token_id(), order.buyer_id, order.price.0);
+        }
+
+        if let Some(order) = matched_order {
+            self.internal_remove_buy_order(order.order_id);
+            if order.price.0 > price {
+                pay_account(&near_token_id(), order.buyer_id.clone(), order.price.0 - price);
+            }
+
+            //process the purchase (which will remove the sale, transfer and get the payout from the nft contract, and then distribute royalties)
+            self.process_purchase(
+                nft_contract_id,
+                token_id,
+                near_token_id(),
+                U128(price),
+                order.buyer_id,
+                None,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::{json, Value};
+
+    fn buy_order(contract: &mut Contract, buyer_id: AccountId, price: u128, expires_at: Option<u64>) -> u64 {
+        call(buyer_id.clone(), ONE_NEAR, NOW);
+        contract.storage_deposit(None);
+        call(buyer_id, price, NOW);
+        contract.place_buy_order(nft(), expires_at.map(U64))
+    }
+
+    fn fixed_price(price: u128) -> Value {
+        json!({ "sale_conditions": { "near": price.to_string() }, "is_auction": false })
+    }
+
+    #[test]
+    fn listings_at_or_below_the_best_order_fill_it_at_the_listing_price() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 500, None);
+        buy_order(&mut contract, carol(), 700, None);
+
+        list(&mut contract, alice(), "1", fixed_price(600), NOW + 1);
+        assert!(sale(&contract, "1").is_none());
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 100)]);
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(carol()));
+        assert_eq!(contract.get_buy_orders(nft(), None, Some(10)).len(), 1);
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), 600)]), NOW + 1);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), 582)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 6),
+                ("near".to_string(), account("commissions.near"), 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn listings_above_every_order_stay_on_the_market() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 500, None);
+
+        list(&mut contract, alice(), "1", fixed_price(600), NOW + 1);
+        assert!(sale(&contract, "1").is_some());
+        assert!(function_calls().iter().all(|(_, method_name, _)| method_name != "nft_transfer_payout"));
+    }
+
+    #[test]
+    fn expired_orders_are_refunded_while_matching() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 600, None);
+        buy_order(&mut contract, carol(), 900, Some(NOW + 1));
+
+        list(&mut contract, alice(), "1", fixed_price(600), NOW + 1);
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 900)]);
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+    }
+
+    #[test]
+    fn only_so_many_orders_are_looked_at_per_listing() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 600, None);
+        for _ in 0..=MAX_OFFERS_SCANNED {
+            buy_order(&mut contract, carol(), 900, Some(NOW + 1));
+        }
+
+        //the first listing only gets as far as refunding the expired orders it looked at
+        list(&mut contract, alice(), "1", fixed_price(600), NOW + 1);
+        assert_eq!(payments().len(), MAX_OFFERS_SCANNED);
+        assert!(sale(&contract, "1").is_some());
+        assert_eq!(contract.get_buy_orders(nft(), None, Some(20)).len(), 2);
+
+        //the next one refunds the last expired order and fills the open one
+        list(&mut contract, alice(), "2", fixed_price(600), NOW + 1);
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 900)]);
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+    }
+
+    #[test]
+    fn the_sellers_own_orders_are_skipped() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 600, None);
+        buy_order(&mut contract, alice(), 900, None);
+
+        list(&mut contract, alice(), "1", fixed_price(600), NOW + 1);
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+        assert_eq!(contract.get_buy_orders_by_buyer_id(alice()).len(), 1);
+    }
+
+    #[test]
+    fn auctions_do_not_fill_orders() {
+        let mut contract = new_market();
+        buy_order(&mut contract, bob(), 5000, None);
+
+        list(&mut contract, alice(), "1", auction_msg(600, json!({})), NOW);
+        assert!(sale(&contract, "1").is_some());
+        assert_eq!(contract.get_buy_orders(nft(), None, Some(10)).len(), 1);
+    }
+
+    #[test]
+    fn cancelling_refunds_the_order() {
+        let mut contract = new_market();
+        let order_id = buy_order(&mut contract, bob(), 500, None);
+
+        call(bob(), 1, NOW + 1);
+        contract.cancel_buy_order(order_id);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 500)]);
+        assert!(contract.get_buy_orders(nft(), None, Some(10)).is_empty());
+    }
+}
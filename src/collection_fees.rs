@@ -0,0 +1,133 @@
+use crate::*;
+use crate::fee_table::assert_valid_fee_table;
+
+#[near_bindgen]
+impl Contract {
+    //sets the fee table for sales from the given collection, replacing the one it had. Collections without
+    //their own fee table use the global one
+    pub fn set_collection_fees(&mut self, nft_contract_id: AccountId, fee_table: Vec<FeeEntry>) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+
+        assert_valid_fee_table(&fee_table);
+        self.collection_fees.insert(&nft_contract_id, &fee_table);
+    }
+
+    //removes the fee table set for a collection so its sales use the global one again
+    pub fn remove_collection_fees(&mut self, nft_contract_id: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+        self.collection_fees.remove(&nft_contract_id);
+    }
+
+    /// views
+    //returns the fees that are taken on a sale from the given collection
+    pub fn get_effective_fees(&self, nft_contract_id: AccountId) -> Vec<FeeEntry> {
+        self.internal_effective_fees(&nft_contract_id)
+    }
+
+    //returns paginated collections with their own fee tables
+    pub fn get_collection_fees(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<(AccountId, Vec<FeeEntry>)> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.collection_fees
+            .iter()
+            .skip(start as usize)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .collect()
+    }
+}
+
+impl Contract {
+    //internal method for getting the fee table for a collection, falling back to the global one
+    pub(crate) fn internal_effective_fees(&self, nft_contract_id: &AccountId) -> Vec<FeeEntry> {
+        self.collection_fees
+            .get(nft_contract_id)
+            .unwrap_or_else(|| self.fee_table.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    fn set_fees(contract: &mut Contract, fee_table: &[(&str, u128)]) {
+        call(account("owner.near"), 0, NOW);
+        let fee_table = fee_table.iter().map(|(account_id, bps)| FeeEntry { account_id: account(account_id), bps: *bps }).collect();
+        contract.set_collection_fees(nft(), fee_table);
+    }
+
+    fn effective_fees(contract: &Contract) -> Vec<(AccountId, u128)> {
+        contract.get_effective_fees(nft()).into_iter().map(|entry| (entry.account_id, entry.bps)).collect()
+    }
+
+    //sells token 1 from alice to bob for 10,000 and returns what the sale paid out
+    fn sell(contract: &mut Contract) -> Vec<(String, AccountId, u128)> {
+        list(contract, alice(), "1", json!({ "sale_conditions": { "near": "10000" }, "is_auction": false }), NOW);
+        call(bob(), 10_000, NOW);
+        contract.offer(nft(), "1".to_string());
+        resolve_purchase(contract, Some(vec![(alice(), 10_000)]), NOW);
+        let mut paid = payments();
+        paid.sort();
+        paid
+    }
+
+    #[test]
+    fn collection_fees_replace_the_global_fees() {
+        let mut contract = new_market();
+        set_fees(&mut contract, &[("partner.near", 50)]);
+
+        assert_eq!(effective_fees(&contract), vec![(account("partner.near"), 50)]);
+        assert_eq!(sell(&mut contract), vec![("near".to_string(), alice(), 9_950)]);
+        assert_eq!(fee_balances(&contract, &[account("partner.near")]), vec![("near".to_string(), account("partner.near"), 50)]);
+    }
+
+    #[test]
+    fn collections_without_their_own_fees_use_the_global_fee_table() {
+        let mut contract = new_market();
+
+        assert_eq!(effective_fees(&contract), vec![(account("charges.near"), 100), (account("commissions.near"), 200)]);
+        assert_eq!(sell(&mut contract), vec![("near".to_string(), alice(), 9_700)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 100),
+                ("near".to_string(), account("commissions.near"), 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn removing_the_collection_fees_restores_the_global_fees() {
+        let mut contract = new_market();
+        set_fees(&mut contract, &[("charges.near", 300)]);
+        contract.remove_collection_fees(nft());
+
+        assert_eq!(effective_fees(&contract), vec![(account("charges.near"), 100), (account("commissions.near"), 200)]);
+        assert!(contract.get_collection_fees(None, Some(10)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot have the sum of fees to be greater than 6000 (60%)")]
+    fn collection_fees_are_capped() {
+        let mut contract = new_market();
+        set_fees(&mut contract, &[("charges.near", 5900), ("commissions.near", 200)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner")]
+    fn only_the_owner_sets_collection_fees() {
+        let mut contract = new_market();
+        call(alice(), 0, NOW);
+        contract.set_collection_fees(nft(), vec![]);
+    }
+}
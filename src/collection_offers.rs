@@ -0,0 +1,307 @@
+use crate::*;
+use crate::internal::{hash_account_id, near_token_id, pay_account};
+use crate::offer_books::{MAX_OFFERS_SCANNED, assert_valid_expiry, insert_into_account_set, insert_into_book, is_expired, remove_from_account_set, remove_from_book};
+
+//struct for an offer to buy any token from a collection. The price is escrowed by the market until the offer is
+//accepted, cancelled or removed after it expires
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionOffer {
+    pub offer_id: u64,
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub price: U128, //in yoctoNEAR
+    pub expires_at: Option<U64>, //Unix timestamp (in nanoseconds) the offer can't be accepted after
+}
+
+impl CollectionOffer {
+    //whether the offer is past its expiry
+    pub(crate) fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //offers to buy any token from the given collection for the attached deposit, which the market holds until
+    //the offer is accepted or cancelled. Like a sale, every open offer takes up one sale worth of storage deposit
+    #[payable]
+    pub fn make_collection_offer(&mut self, nft_contract_id: AccountId, expires_at: Option<U64>) -> u64 {
+        let price = env::attached_deposit();
+        assert!(price > 0, "Attached deposit must be greater than 0");
+
+        assert_valid_expiry(expires_at);
+
+        //make sure the buyer has paid for the storage of one more offer
+        let buyer_id = env::predecessor_account_id();
+        self.internal_assert_offer_storage(&buyer_id);
+
+        let offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+
+        let offer = CollectionOffer {
+            offer_id,
+            buyer_id: buyer_id.clone(),
+            nft_contract_id: nft_contract_id.clone(),
+            price: U128(price),
+            expires_at,
+        };
+        self.collection_offers.insert(&offer_id, &offer);
+
+        //add the offer to the collection's offer book and to the buyer's offers. We get new unique prefixes by hashing the accounts
+        insert_into_book(
+            &mut self.collection_offer_book,
+            &nft_contract_id,
+            StorageKey::CollectionOfferBookInner { account_id_hash: hash_account_id(&nft_contract_id) },
+            price,
+            offer_id,
+        );
+        insert_into_account_set(
+            &mut self.collection_offers_by_buyer_id,
+            &buyer_id,
+            StorageKey::CollectionOffersByBuyerIdInner { account_id_hash: hash_account_id(&buyer_id) },
+            offer_id,
+        );
+
+        offer_id
+    }
+
+    //cancels a collection offer and refunds it. The buyer can cancel at any time and anyone can remove an expired offer
+    #[payable]
+    pub fn cancel_collection_offer(&mut self, offer_id: u64) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+        let offer = self.collection_offers.get(&offer_id).expect("No collection offer");
+
+        if env::predecessor_account_id() != offer.buyer_id {
+            assert!(offer.is_expired(), "Only the buyer can cancel an offer before it expires");
+        }
+
+        let offer = self.internal_remove_collection_offer(offer_id);
+        pay_account(&near_token_id(), offer.buyer_id, offer.price.0);
+    }
+
+    /// views
+    //returns paginated collection offers for the given collection, highest price first. Ties go to the oldest offer
+    pub fn get_collection_offers(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CollectionOffer> {
+        let offer_book = match self.collection_offer_book.get(&nft_contract_id) {
+            Some(offer_book) => offer_book,
+            None => return vec![],
+        };
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        offer_book
+            .iter_rev()
+            .skip(start as usize)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .map(|(_, offer_id)| self.collection_offers.get(&offer_id).unwrap())
+            .collect()
+    }
+
+    //returns the offer that would be accepted right now for a token from the given collection
+    pub fn get_best_collection_offer(&self, nft_contract_id: AccountId) -> Option<CollectionOffer> {
+        self.collection_offer_book.get(&nft_contract_id).and_then(|offer_book| {
+            offer_book
+                .iter_rev()
+                .map(|(_, offer_id)| self.collection_offers.get(&offer_id).unwrap())
+                .find(|offer| !offer.is_expired())
+        })
+    }
+
+    //returns every collection offer made by the given buyer
+    pub fn get_collection_offers_by_buyer_id(&self, buyer_id: AccountId) -> Vec<CollectionOffer> {
+        self.collection_offers_by_buyer_id
+            .get(&buyer_id)
+            .map(|by_buyer_id| {
+                by_buyer_id
+                    .iter()
+                    .map(|offer_id| self.collection_offers.get(&offer_id).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    //internal method for removing a collection offer from the market. The escrowed price is not refunded here
+    pub(crate) fn internal_remove_collection_offer(&mut self, offer_id: u64) -> CollectionOffer {
+        let offer = self.collection_offers.remove(&offer_id).expect("No collection offer");
+
+        remove_from_book(&mut self.collection_offer_book, &offer.nft_contract_id, offer.price.0, offer_id);
+        remove_from_account_set(&mut self.collection_offers_by_buyer_id, &offer.buyer_id, offer_id);
+
+        offer
+    }
+
+    //internal method for finding the best collection offer the given owner can accept for a token from the given
+    //collection. Their own offers are skipped. Expired offers found along the way are removed and refunded and only
+    //the first MAX_OFFERS_SCANNED offers are looked at
+    pub(crate) fn internal_best_collection_offer(&mut self, nft_contract_id: &AccountId, owner_id: &AccountId) -> Option<CollectionOffer> {
+        let offer_book = self.collection_offer_book.get(nft_contract_id)?;
+
+        let mut expired_offer_ids = Vec::new();
+        let mut best_offer = None;
+        for (_, offer_id) in offer_book.iter_rev().take(MAX_OFFERS_SCANNED) {
+            let offer = self.collection_offers.get(&offer_id).unwrap();
+            if offer.is_expired() {
+                expired_offer_ids.push(offer_id);
+            } else if offer.buyer_id != *owner_id {
+                best_offer = Some(offer);
+                break;
+            }
+        }
+
+        for offer_id in expired_offer_ids {
+            let offer = self.internal_remove_collection_offer(offer_id);
+            pay_account(&near_token_id(), offer.buyer_id, offer.price.0);
+        }
+
+        best_offer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    fn offer(contract: &mut Contract, buyer_id: AccountId, price: u128, expires_at: Option<u64>) -> u64 {
+        call(buyer_id.clone(), ONE_NEAR, NOW);
+        contract.storage_deposit(None);
+        call(buyer_id, price, NOW);
+        contract.make_collection_offer(nft(), expires_at.map(U64))
+    }
+
+    fn accept(contract: &mut Contract, owner_id: AccountId, token_id: &str, min_price: Option<u128>, timestamp: u64) {
+        let msg = match min_price {
+            Some(min_price) => json!({ "accept_offer": { "min_price": min_price.to_string() } }),
+            None => json!({ "accept_offer": {} }),
+        };
+        approve(contract, owner_id, token_id, msg, timestamp);
+    }
+
+    #[test]
+    fn best_offer_is_the_highest_and_then_the_oldest() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+        let first = offer(&mut contract, carol(), 700, None);
+        offer(&mut contract, bob(), 700, None);
+
+        assert_eq!(contract.get_best_collection_offer(nft()).unwrap().offer_id, first);
+        let prices: Vec<u128> = contract.get_collection_offers(nft(), None, Some(10)).into_iter().map(|offer| offer.price.0).collect();
+        assert_eq!(prices, vec![700, 700, 500]);
+        assert_eq!(contract.get_collection_offers_by_buyer_id(bob()).len(), 2);
+    }
+
+    #[test]
+    fn accepting_sells_the_token_to_the_best_offer() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+
+        accept(&mut contract, alice(), "7", None, NOW + 1);
+        let transfer = scheduled_call("nft_transfer_payout");
+        assert_eq!(transfer["receiver_id"], json!(bob()));
+        assert_eq!(transfer["token_id"], "7");
+        assert!(contract.get_best_collection_offer(nft()).is_none());
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), 500)]), NOW + 1);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), 485)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 5),
+                ("near".to_string(), account("commissions.near"), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn expired_offers_are_refunded_instead_of_accepted() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+        offer(&mut contract, carol(), 900, Some(NOW + 10));
+
+        accept(&mut contract, alice(), "7", None, NOW + 10);
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 900)]);
+        assert_eq!(scheduled_call("resolve_purchase")["buyer_id"], json!(bob()));
+    }
+
+    #[test]
+    fn the_owners_own_offers_are_skipped() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+        let own_offer = offer(&mut contract, alice(), 900, None);
+
+        accept(&mut contract, alice(), "7", None, NOW + 1);
+        assert_eq!(scheduled_call("resolve_purchase")["buyer_id"], json!(bob()));
+        assert_eq!(contract.get_best_collection_offer(nft()).unwrap().offer_id, own_offer);
+    }
+
+    #[test]
+    #[should_panic(expected = "Offer 500 is below the minimum price 600")]
+    fn offers_below_the_minimum_price_are_not_accepted() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+
+        accept(&mut contract, alice(), "7", Some(600), NOW + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is being auctioned. End or remove the auction before accepting an offer")]
+    fn auctioned_tokens_have_to_be_removed_before_accepting() {
+        let mut contract = new_market();
+        offer(&mut contract, bob(), 500, None);
+        list(&mut contract, alice(), "7", auction_msg(1000, json!({})), NOW);
+
+        accept(&mut contract, alice(), "7", None, NOW + 1);
+    }
+
+    #[test]
+    fn cancelling_refunds_the_offer() {
+        let mut contract = new_market();
+        let offer_id = offer(&mut contract, bob(), 500, None);
+
+        call(bob(), 1, NOW + 1);
+        contract.cancel_collection_offer(offer_id);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 500)]);
+        assert!(contract.get_collection_offers_by_buyer_id(bob()).is_empty());
+    }
+
+    #[test]
+    fn anyone_can_remove_an_expired_offer() {
+        let mut contract = new_market();
+        let offer_id = offer(&mut contract, bob(), 500, Some(NOW + 10));
+
+        call(carol(), 1, NOW + 10);
+        contract.cancel_collection_offer(offer_id);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 500)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the buyer can cancel an offer before it expires")]
+    fn only_the_buyer_can_cancel_an_open_offer() {
+        let mut contract = new_market();
+        let offer_id = offer(&mut contract, bob(), 500, Some(NOW + 10));
+
+        call(carol(), 1, NOW + 9);
+        contract.cancel_collection_offer(offer_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage paid")]
+    fn offers_take_up_storage() {
+        let mut contract = new_market();
+
+        call(bob(), 500, NOW);
+        contract.make_collection_offer(nft(), None);
+    }
+}
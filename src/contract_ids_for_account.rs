@@ -39,7 +39,7 @@ impl Contract{
 		let mut contract_ids = self.contract_ids_by_account_id.get(&account_id).expect("Couldn't find account");
 
         // Finding the contract id
-        assert_eq!(contract_ids.contains(&nft_contract_id), true, "Couldn't find the contract being removed");
+        assert!(contract_ids.contains(&nft_contract_id), "Couldn't find the contract being removed");
         
         contract_ids.remove(&nft_contract_id);
         
@@ -0,0 +1,35 @@
+use crate::*;
+use near_sdk::serde_json::{json, Value};
+
+//standard and version of the events the market logs
+const EVENT_STANDARD: &str = "nft_market";
+const EVENT_VERSION: &str = "1.0.0";
+
+//logs an event in the NEP-297 format ("EVENT_JSON:" followed by the event as JSON) so indexers can parse it
+pub(crate) fn log_event(event: &str, data: Value) {
+    let event = json!({
+        "standard": EVENT_STANDARD,
+        "version": EVENT_VERSION,
+        "event": event,
+        "data": [data],
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn events_are_logged_in_the_nep_297_format() {
+        testing_env!(VMContextBuilder::new().build());
+        log_event("auction_relisted", json!({ "token_id": "1", "relists_left": 2 }));
+
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"data":[{"relists_left":2,"token_id":"1"}],"event":"auction_relisted","standard":"nft_market","version":"1.0.0"}"#]
+        );
+    }
+}
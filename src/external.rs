@@ -20,4 +20,16 @@ trait ExtContract {
         //the maximum amount of accounts the market can payout at once (this is limited by GAS)
 		max_len_payout: u32,
     );
-}
\ No newline at end of file
+}
+
+//initiate a cross contract call to a fungible token contract. This is used to pay out
+//royalties and fees for sales that were priced in that token.
+#[ext_contract(ext_ft)]
+trait ExtFungibleToken {
+    fn ft_transfer(
+        &mut self,
+        receiver_id: AccountId, //account to send the tokens to
+        amount: U128, //amount of tokens to send
+        memo: Option<String>, //memo (to include some context)
+    );
+}
@@ -0,0 +1,197 @@
+use crate::*;
+use crate::internal::{hash_account_id, near_token_id, pay_account};
+
+#[near_bindgen]
+impl Contract {
+    //withdraws every fee the caller has been credited in the given fungible token (NEAR if none is passed in) in a
+    //single payment. If the payment fails, the amount is added to the caller's pending balance
+    #[payable]
+    pub fn withdraw_fees(&mut self, ft_token_id: Option<FungibleTokenId>) -> U128 {
+        //make sure the user attaches exactly 1 yoctoNEAR for security purposes.
+        //this will redirect them to the NEAR wallet (or requires a full access key).
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let ft_token_id = ft_token_id.unwrap_or_else(near_token_id);
+
+        //get the fee balances of the account. If there are none, panic
+        let mut balances = self.fee_balances.get(&account_id).expect("No fees to withdraw");
+        let amount = balances.remove(&ft_token_id).expect("No fees to withdraw");
+
+        //if the account has no fees left in other fungible tokens, we remove it from the map
+        if balances.is_empty() {
+            self.fee_balances.remove(&account_id);
+        } else {
+            self.fee_balances.insert(&account_id, &balances);
+        }
+
+        pay_account(&ft_token_id, account_id, amount);
+        U128(amount)
+    }
+
+    /// views
+    //returns the fees an account can withdraw for every fungible token ("near" for NEAR)
+    pub fn get_fee_balances(&self, account_id: AccountId) -> Vec<(FungibleTokenId, U128)> {
+        self.fee_balances
+            .get(&account_id)
+            .map(|balances| balances.iter().map(|(ft_token_id, amount)| (ft_token_id, U128(amount))).collect())
+            .unwrap_or_default()
+    }
+
+    //returns the fees accrued on a collection's sales since the ledger started, for every fungible token
+    pub fn get_fees_accrued_for_collection(&self, nft_contract_id: AccountId) -> Vec<(FungibleTokenId, U128)> {
+        self.fees_accrued
+            .get(&nft_contract_id)
+            .map(|accrued| accrued.iter().map(|(ft_token_id, amount)| (ft_token_id, U128(amount))).collect())
+            .unwrap_or_default()
+    }
+
+    //returns paginated collections with the fees accrued on their sales for every fungible token
+    pub fn get_fees_accrued(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(AccountId, Vec<(FungibleTokenId, U128)>)> {
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.fees_accrued
+            .iter()
+            .skip(start as usize)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .map(|(nft_contract_id, accrued)| {
+                (
+                    nft_contract_id,
+                    accrued.iter().map(|(ft_token_id, amount)| (ft_token_id, U128(amount))).collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    //internal method for crediting a fee from a sale of the given collection to the recipient's fee balance
+    pub(crate) fn internal_credit_fee(
+        &mut self,
+        nft_contract_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        account_id: &AccountId,
+        amount: u128,
+    ) {
+        //get the fee balances of the recipient. If there are none, we create a new empty map
+        let mut balances = self.fee_balances.get(account_id).unwrap_or_else(|| {
+            UnorderedMap::new(
+                StorageKey::FeeBalancesInner {
+                    //we get a new unique prefix for the collection by hashing the account
+                    account_id_hash: hash_account_id(account_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        let balance = balances.get(ft_token_id).unwrap_or(0);
+        balances.insert(ft_token_id, &(balance + amount));
+        self.fee_balances.insert(account_id, &balances);
+
+        //get the fees accrued on the collection. If there are none, we create a new empty map
+        let mut accrued = self.fees_accrued.get(nft_contract_id).unwrap_or_else(|| {
+            UnorderedMap::new(
+                StorageKey::FeesAccruedInner {
+                    //we get a new unique prefix for the collection by hashing the NFT contract
+                    account_id_hash: hash_account_id(nft_contract_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        let total = accrued.get(ft_token_id).unwrap_or(0);
+        accrued.insert(ft_token_id, &(total + amount));
+        self.fees_accrued.insert(nft_contract_id, &accrued);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ft_callbacks::FungibleTokenReceiver;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    //sells the token from alice to bob for 10,000 in NEAR or in USDC
+    fn sell(contract: &mut Contract, token_id: &str, ft_token_id: &str) {
+        let msg = json!({ "sale_conditions": { ft_token_id: "10000" }, "is_auction": false });
+        list(contract, alice(), token_id, msg, NOW);
+        if ft_token_id == NEAR_TOKEN_ID {
+            call(bob(), 10_000, NOW);
+            contract.offer(nft(), token_id.to_string());
+        } else {
+            call(usdc(), 0, NOW);
+            contract.ft_on_transfer(bob(), U128(10_000), json!({ "nft_contract_id": nft(), "token_id": token_id }).to_string());
+        }
+        resolve_purchase(contract, Some(vec![(alice(), 10_000)]), NOW);
+    }
+
+    fn market_with_usdc() -> Contract {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(usdc());
+        contract
+    }
+
+    #[test]
+    fn fees_build_up_per_token_until_they_are_withdrawn() {
+        let mut contract = market_with_usdc();
+        sell(&mut contract, "1", "near");
+        sell(&mut contract, "2", "near");
+        sell(&mut contract, "3", "usdc.near");
+
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near")]),
+            vec![("near".to_string(), account("charges.near"), 200), ("usdc.near".to_string(), account("charges.near"), 100)]
+        );
+        assert_eq!(
+            contract.get_fees_accrued_for_collection(nft()),
+            vec![(account("near"), U128(600)), (usdc(), U128(300))]
+        );
+    }
+
+    #[test]
+    fn withdrawing_pays_the_fees_in_one_payment_and_keeps_the_accrued_totals() {
+        let mut contract = market_with_usdc();
+        sell(&mut contract, "1", "near");
+        sell(&mut contract, "2", "near");
+        sell(&mut contract, "3", "usdc.near");
+
+        call(account("charges.near"), 1, NOW);
+        assert_eq!(contract.withdraw_fees(None), U128(200));
+        assert_eq!(payments(), vec![("near".to_string(), account("charges.near"), 200)]);
+        assert_eq!(contract.get_fee_balances(account("charges.near")), vec![(usdc(), U128(100))]);
+
+        call(account("charges.near"), 1, NOW);
+        assert_eq!(contract.withdraw_fees(Some(usdc())), U128(100));
+        assert_eq!(payments(), vec![("usdc.near".to_string(), account("charges.near"), 100)]);
+        assert!(contract.get_fee_balances(account("charges.near")).is_empty());
+        assert_eq!(contract.get_fees_accrued(None, Some(10)).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "No fees to withdraw")]
+    fn fees_can_only_be_withdrawn_once() {
+        let mut contract = new_market();
+        sell(&mut contract, "1", "near");
+
+        call(account("charges.near"), 1, NOW);
+        contract.withdraw_fees(None);
+        contract.withdraw_fees(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "No fees to withdraw")]
+    fn accounts_without_fees_have_nothing_to_withdraw() {
+        let mut contract = new_market();
+        sell(&mut contract, "1", "near");
+
+        call(alice(), 1, NOW);
+        contract.withdraw_fees(None);
+    }
+}
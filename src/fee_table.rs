@@ -0,0 +1,217 @@
+use crate::*;
+
+//most accounts a fee table can pay. Every entry is paid in its own transfer
+const MAX_FEE_ENTRIES: usize = 10;
+//the fees in a fee table can't add up to more than this (in basis points)
+const MAX_TOTAL_FEES: u128 = 6000; // 60%
+
+//struct for one recipient of the fees the market takes on a sale
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeEntry {
+    pub account_id: AccountId,
+    pub bps: u128, //part of the price (in basis points) paid to the account
+}
+
+//makes sure a fee table has at most MAX_FEE_ENTRIES distinct accounts and adds up to at most MAX_TOTAL_FEES
+pub(crate) fn assert_valid_fee_table(fee_table: &[FeeEntry]) {
+    assert!(fee_table.len() <= MAX_FEE_ENTRIES, "Cannot have more than {} fee entries", MAX_FEE_ENTRIES);
+
+    let mut total: u128 = 0;
+    for (index, entry) in fee_table.iter().enumerate() {
+        assert!(entry.bps > 0, "Fee for {} must be greater than 0", entry.account_id);
+        assert!(
+            fee_table[..index].iter().all(|other| other.account_id != entry.account_id),
+            "{} is in the fee table more than once",
+            entry.account_id
+        );
+        total += entry.bps;
+    }
+
+    assert!(
+        total <= MAX_TOTAL_FEES,
+        "Cannot have the sum of fees to be greater than {} ({}%)",
+        MAX_TOTAL_FEES,
+        MAX_TOTAL_FEES / 100
+    );
+}
+
+//the fee table of a market that takes the given charges and commissions. Fees of 0 are left out and the two are added
+//up into one entry when they go to the same account
+pub(crate) fn charges_and_commissions_fee_table(
+    charges_id: AccountId,
+    charges: u128,
+    commissions_id: AccountId,
+    commissions: u128,
+) -> Vec<FeeEntry> {
+    let mut fee_table: Vec<FeeEntry> = Vec::new();
+    for (account_id, bps) in [(charges_id, charges), (commissions_id, commissions)] {
+        if bps == 0 {
+            continue;
+        }
+        match fee_table.iter_mut().find(|entry| entry.account_id == account_id) {
+            Some(entry) => entry.bps += bps,
+            None => fee_table.push(FeeEntry { account_id, bps }),
+        }
+    }
+    fee_table
+}
+
+#[near_bindgen]
+impl Contract {
+    //replaces the fee table that is used for every collection without its own fees
+    pub fn set_fee_table(&mut self, fee_table: Vec<FeeEntry>) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+
+        assert_valid_fee_table(&fee_table);
+        self.fee_table = fee_table;
+    }
+
+    //adds an account to the fee table or changes its fee. A fee of 0 removes the account from the table
+    pub fn set_fee_entry(&mut self, account_id: AccountId, bps: u128) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+
+        let mut fee_table = self.fee_table.clone();
+        match fee_table.iter().position(|entry| entry.account_id == account_id) {
+            Some(index) if bps == 0 => {
+                fee_table.remove(index);
+            }
+            Some(index) => fee_table[index].bps = bps,
+            None if bps == 0 => env::panic_str("Account is not in the fee table"),
+            None => fee_table.push(FeeEntry { account_id, bps }),
+        }
+
+        assert_valid_fee_table(&fee_table);
+        self.fee_table = fee_table;
+    }
+
+    /// views
+    //returns the fee table used for every collection without its own fees
+    pub fn get_fee_table(&self) -> Vec<FeeEntry> {
+        self.fee_table.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    fn fee_entry(account_id: &str, bps: u128) -> FeeEntry {
+        FeeEntry { account_id: account(account_id), bps }
+    }
+
+    fn fee_table(contract: &Contract) -> Vec<(AccountId, u128)> {
+        contract.get_fee_table().into_iter().map(|entry| (entry.account_id, entry.bps)).collect()
+    }
+
+    #[test]
+    fn fee_table_within_the_limits_is_valid() {
+        assert_valid_fee_table(&[]);
+        assert_valid_fee_table(&[fee_entry("charges.near", 4000), fee_entry("commissions.near", 2000)]);
+        let fee_table: Vec<FeeEntry> = (0..MAX_FEE_ENTRIES).map(|i| fee_entry(&format!("fee{}.near", i), 100)).collect();
+        assert_valid_fee_table(&fee_table);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot have more than 10 fee entries")]
+    fn fee_table_with_too_many_entries_is_invalid() {
+        let fee_table: Vec<FeeEntry> = (0..=MAX_FEE_ENTRIES).map(|i| fee_entry(&format!("fee{}.near", i), 100)).collect();
+        assert_valid_fee_table(&fee_table);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee for charges.near must be greater than 0")]
+    fn fee_table_with_a_zero_fee_is_invalid() {
+        assert_valid_fee_table(&[fee_entry("charges.near", 0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "charges.near is in the fee table more than once")]
+    fn fee_table_with_a_duplicate_account_is_invalid() {
+        assert_valid_fee_table(&[fee_entry("charges.near", 100), fee_entry("commissions.near", 100), fee_entry("charges.near", 100)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot have the sum of fees to be greater than 6000 (60%)")]
+    fn fee_table_over_the_total_is_invalid() {
+        assert_valid_fee_table(&[fee_entry("charges.near", 4000), fee_entry("commissions.near", 2001)]);
+    }
+
+    #[test]
+    fn new_leaves_fees_of_zero_out_of_the_fee_table() {
+        call(account("owner.near"), 0, NOW);
+        let contract = Contract::new(account("owner.near"), account("charges.near"), 0, account("commissions.near"), 200);
+
+        assert_eq!(fee_table(&contract), vec![(account("commissions.near"), 200)]);
+    }
+
+    #[test]
+    fn new_adds_up_charges_and_commissions_to_the_same_account() {
+        call(account("owner.near"), 0, NOW);
+        let contract = Contract::new(account("owner.near"), account("fees.near"), 300, account("fees.near"), 200);
+
+        assert_eq!(fee_table(&contract), vec![(account("fees.near"), 500)]);
+    }
+
+    #[test]
+    fn set_fee_entry_adds_changes_and_removes_accounts() {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+
+        contract.set_fee_entry(account("partner.near"), 50);
+        contract.set_fee_entry(account("charges.near"), 150);
+        contract.set_fee_entry(account("commissions.near"), 0);
+        assert_eq!(fee_table(&contract), vec![(account("charges.near"), 150), (account("partner.near"), 50)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot have the sum of fees to be greater than 6000 (60%)")]
+    fn set_fee_entry_keeps_the_fee_table_under_the_cap() {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+
+        contract.set_fee_entry(account("partner.near"), 5701);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner")]
+    fn only_the_owner_sets_the_fee_table() {
+        let mut contract = new_market();
+        call(alice(), 0, NOW);
+
+        contract.set_fee_table(vec![]);
+    }
+
+    #[test]
+    fn every_account_in_the_fee_table_is_paid() {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.set_fee_table(vec![fee_entry("charges.near", 100), fee_entry("commissions.near", 200), fee_entry("partner.near", 50)]);
+
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "10000" }, "is_auction": false }), NOW);
+        call(bob(), 10_000, NOW);
+        contract.offer(nft(), "1".to_string());
+        resolve_purchase(&mut contract, Some(vec![(alice(), 10_000)]), NOW);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), 9_650)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near"), account("partner.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 100),
+                ("near".to_string(), account("commissions.near"), 200),
+                ("near".to_string(), account("partner.near"), 50),
+            ]
+        );
+    }
+}
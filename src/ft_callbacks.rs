@@ -0,0 +1,344 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+
+/// transfer callbacks from FT Contracts
+
+//struct for keeping track of which sale or auction the tokens sent through ft_transfer_call are for
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseArgs {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    #[serde(default)]
+    pub buy_now: bool, //buy an auction at its buy now price instead of bidding on it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bid_hash: Option<Base64VecU8>, //commitment for a sealed bid auction. The tokens sent are the deposit
+    #[serde(default)]
+    pub proxy: bool, //bid on an auction as a proxy bid. The tokens sent are the maximum bid
+}
+
+/*
+    trait that will be used as the callback from the FT contract. When ft_transfer_call is
+    called, it will fire a cross contract call to this marketplace and this is the function
+    that is invoked. 
+*/
+pub trait FungibleTokenReceiver {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+//implementation of the trait
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// where we buy a sale or bid on an auction priced in a fungible token. The returned value is the amount
+    /// of tokens that weren't used, which the FT contract refunds to the sender in ft_resolve_transfer
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        //the fungible token contract is the one calling this function
+        let ft_token_id = env::predecessor_account_id();
+
+        //"near" stands for NEAR in sales but it's also an account anyone could call this from. Tokens sent from it
+        //would be paid out as NEAR the market never received
+        assert_ne!(ft_token_id.as_str(), NEAR_TOKEN_ID, "NEAR has to be attached as a deposit");
+
+        //make sure the marketplace accepts the token. If not, the panic refunds the sender
+        assert!(
+            self.ft_token_ids.contains(&ft_token_id),
+            "Token {} is not accepted by the marketplace",
+            ft_token_id
+        );
+
+        let PurchaseArgs {
+            nft_contract_id,
+            token_id,
+            buy_now,
+            bid_hash,
+            proxy,
+        } = near_sdk::serde_json::from_str(&msg).expect("Not valid PurchaseArgs");
+
+        //get the unique sale ID (contract + DELIMITER + token ID)
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        //get the sale object from the unique sale ID. If the sale doesn't exist, panic.
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+
+        //the sender of the tokens is the buyer, make sure they're not the owner of the sale
+        assert_ne!(sale.owner_id, sender_id, "Cannot bid on your own sale.");
+
+        //tokens sent for a sealed bid auction are the deposit for a committed bid. The market keeps them until the auction ends
+        if sale.is_sealed_bid {
+            self.internal_commit_bid(
+                nft_contract_id,
+                token_id,
+                ft_token_id,
+                sender_id,
+                bid_hash.expect("Sealed bid auctions need a bid_hash"),
+                amount.0,
+            );
+            return PromiseOrValue::Value(U128(0));
+        }
+
+        //tokens sent to buy an auction outright are kept by the market. If the purchase fails, resolve_purchase refunds them
+        if sale.is_auction && buy_now {
+            self.internal_buy_now(
+                nft_contract_id,
+                token_id,
+                ft_token_id,
+                sender_id,
+                amount.0,
+            );
+            return PromiseOrValue::Value(U128(0));
+        }
+
+        //tokens sent for an auction are a bid. The market keeps all of them until the bidder is outbid or the auction ends
+        if sale.is_auction {
+            self.internal_add_bid(
+                nft_contract_id,
+                token_id,
+                ft_token_id,
+                sender_id,
+                amount.0,
+                proxy,
+            );
+            return PromiseOrValue::Value(U128(0));
+        }
+
+        //a dutch auction is bought at its current price. Anything sent above that is refunded right away and
+        //the price itself is refunded through ft_resolve_transfer if the purchase fails
+        if sale.is_dutch_auction {
+            return self
+                .internal_buy_dutch_auction(
+                    nft_contract_id,
+                    token_id,
+                    ft_token_id,
+                    sender_id,
+                    amount.0,
+                )
+                .into();
+        }
+
+        //get the price of the sale in the tokens that were sent. If the sale can't be bought with them, panic.
+        let price = sale.sale_conditions.get(&ft_token_id).expect("This sale is not priced in the sent token").0;
+
+        //make sure the sale has started and isn't over
+        sale.assert_listing_open();
+
+        //make sure the amount is greater than the price
+        assert!(amount.0 >= price, "Transferred amount must be greater than or equal to the current price: {:?}", price);
+
+        //process the purchase (which will remove the sale, transfer and get the payout from the nft contract, and then distribute royalties).
+        //the value of the promise is what resolve_purchase returns, which is the amount to refund to the buyer
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            ft_token_id,
+            amount,
+            sender_id,
+            None,
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    fn usdc_sale() -> Contract {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(usdc());
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "usdc.near": "1000" }, "is_auction": false }), NOW);
+        contract
+    }
+
+    #[test]
+    fn ft_purchase_pays_the_seller_and_fees_in_the_token() {
+        let mut contract = usdc_sale();
+
+        call(usdc(), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+        assert!(sale(&contract, "1").is_none());
+
+        let unused = resolve_purchase(&mut contract, Some(vec![(alice(), 1000)]), NOW);
+        assert_eq!(unused, U128(0));
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("usdc.near".to_string(), alice(), 970)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("usdc.near".to_string(), account("charges.near"), 10),
+                ("usdc.near".to_string(), account("commissions.near"), 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn ft_purchase_is_refunded_by_the_token_when_the_transfer_fails() {
+        let mut contract = usdc_sale();
+
+        call(usdc(), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+
+        //the unused amount goes back to the buyer through ft_resolve_transfer, so the market sends nothing itself
+        let unused = resolve_purchase(&mut contract, None, NOW);
+        assert_eq!(unused, U128(1000));
+        assert!(payments().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "This sale is not priced in the sent token")]
+    fn ft_purchase_in_another_token_is_rejected() {
+        let mut contract = usdc_sale();
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(account("dai.near"));
+
+        call(account("dai.near"), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token dai.near is not accepted by the marketplace")]
+    fn ft_purchase_in_a_token_that_is_not_accepted_is_rejected() {
+        let mut contract = usdc_sale();
+
+        call(account("dai.near"), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "This sale is not priced in NEAR. Please use ft_transfer_call on one of its tokens to buy it!")]
+    fn near_offer_on_a_token_sale_is_rejected() {
+        let mut contract = usdc_sale();
+
+        call(bob(), 1000, NOW);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Transferred amount must be greater than or equal to the current price")]
+    fn ft_purchase_below_the_price_is_rejected() {
+        let mut contract = usdc_sale();
+
+        call(usdc(), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(999), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "NEAR has to be attached as a deposit")]
+    fn ft_purchase_from_the_near_account_is_rejected() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "1000" }, "is_auction": false }), NOW);
+
+        call(account("near"), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+    }
+
+    //an auction of token 2 by alice in USDC, starting at 1000 and running until NOW + 1000
+    fn usdc_auction() -> Contract {
+        let mut contract = usdc_sale();
+        list(
+            &mut contract,
+            alice(),
+            "2",
+            json!({
+                "sale_conditions": { "usdc.near": "1000" },
+                "is_auction": true,
+                "start_time": NOW.to_string(),
+                "end_time": (NOW + 1000).to_string(),
+            }),
+            NOW,
+        );
+        contract
+    }
+
+    fn ft_bid(contract: &mut Contract, bidder_id: AccountId, amount: u128) -> U128 {
+        deposit_storage(contract, bidder_id.clone(), NOW + 1);
+        call(usdc(), 0, NOW + 1);
+        match contract.ft_on_transfer(bidder_id, U128(amount), json!({ "nft_contract_id": nft(), "token_id": "2" }).to_string()) {
+            PromiseOrValue::Value(unused) => unused,
+            PromiseOrValue::Promise(_) => panic!("bids don't call out"),
+        }
+    }
+
+    #[test]
+    fn ft_bids_are_kept_and_the_outbid_bidder_is_refunded_in_the_token() {
+        let mut contract = usdc_auction();
+
+        assert_eq!(ft_bid(&mut contract, bob(), 1001), U128(0));
+        assert!(payments().is_empty());
+
+        assert_eq!(ft_bid(&mut contract, carol(), 1002), U128(0));
+        assert_eq!(payments(), vec![("usdc.near".to_string(), bob(), 1001)]);
+        let bids = sale(&contract, "2").unwrap().bids.unwrap();
+        assert_eq!(bids.last().unwrap().bidder_id, carol());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't pay less than or equal to current bid price + increment (1)")]
+    fn ft_bids_have_to_beat_the_current_bid() {
+        let mut contract = usdc_auction();
+
+        ft_bid(&mut contract, bob(), 1001);
+        ft_bid(&mut contract, carol(), 1001);
+    }
+
+    #[test]
+    #[should_panic(expected = "Please use storage_deposit")]
+    fn ft_bidders_pay_for_their_bids_out_of_their_storage_deposit() {
+        let mut contract = usdc_auction();
+
+        call(usdc(), 0, NOW + 1);
+        contract.ft_on_transfer(bob(), U128(1001), json!({ "nft_contract_id": nft(), "token_id": "2" }).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "This auction is priced in usdc.near")]
+    fn near_bids_on_a_token_auction_are_rejected() {
+        let mut contract = usdc_auction();
+
+        call(bob(), ONE_NEAR, NOW + 1);
+        contract.add_bid(nft(), "2".to_string());
+    }
+
+    #[test]
+    fn token_auctions_settle_in_the_token_and_refund_the_winner_if_the_transfer_fails() {
+        let mut contract = usdc_auction();
+        ft_bid(&mut contract, bob(), 2000);
+
+        call(alice(), 0, NOW + 1001);
+        contract.end_auction(nft(), "2".to_string());
+        assert_eq!(scheduled_call("resolve_purchase")["ft_token_id"], json!("usdc.near"));
+
+        resolve_purchase(&mut contract, None, NOW + 1001);
+        assert_eq!(payments(), vec![("usdc.near".to_string(), bob(), 2000)]);
+    }
+
+    #[test]
+    fn token_auctions_can_be_bought_now_with_the_token() {
+        let mut contract = usdc_sale();
+        let mut msg = auction_msg(1000, json!({ "buy_now_price": "5000" }));
+        msg["sale_conditions"] = json!({ "usdc.near": "1000" });
+        list(&mut contract, alice(), "2", msg, NOW);
+
+        call(usdc(), 0, NOW + 1);
+        contract.ft_on_transfer(bob(), U128(5000), json!({ "nft_contract_id": nft(), "token_id": "2", "buy_now": true }).to_string());
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("5000"));
+
+        //a failed transfer sends the tokens the market holds back to the buyer
+        resolve_purchase(&mut contract, None, NOW + 1);
+        assert_eq!(payments(), vec![("usdc.near".to_string(), bob(), 5000)]);
+    }
+}
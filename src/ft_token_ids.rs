@@ -0,0 +1,99 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+
+    //adds a fungible token to the list of tokens that sales can be priced in
+    pub fn add_ft_token_id(&mut self, ft_token_id: FungibleTokenId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+
+        assert!(self.ft_token_ids.insert(&ft_token_id), "Token is already accepted");
+    }
+
+    /*
+        removes a fungible token from the accepted tokens. No new sales can be listed or bought in the token after this.
+        Sales that are already priced in it stay on the market so their owners can either price them in another
+        currency with update_price or take them down with remove_sale.
+    */
+    pub fn remove_ft_token_id(&mut self, ft_token_id: FungibleTokenId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "only owner"
+        );
+
+        assert_ne!(ft_token_id.as_str(), NEAR_TOKEN_ID, "Cannot remove NEAR");
+
+        assert!(self.ft_token_ids.remove(&ft_token_id), "Couldn't find the token being removed");
+    }
+
+    //returns the fungible tokens that sales can be priced in
+    pub fn get_ft_token_ids(&self) -> Vec<FungibleTokenId> {
+        self.ft_token_ids.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    #[test]
+    fn near_is_accepted_from_the_start_and_the_owner_manages_the_rest() {
+        let mut contract = new_market();
+        assert_eq!(contract.get_ft_token_ids(), vec![account("near")]);
+
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(usdc());
+        assert_eq!(contract.get_ft_token_ids(), vec![account("near"), usdc()]);
+
+        contract.remove_ft_token_id(usdc());
+        assert_eq!(contract.get_ft_token_ids(), vec![account("near")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner")]
+    fn only_the_owner_can_add_tokens() {
+        let mut contract = new_market();
+
+        call(alice(), 0, NOW);
+        contract.add_ft_token_id(usdc());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot remove NEAR")]
+    fn near_cannot_be_removed() {
+        let mut contract = new_market();
+
+        call(account("owner.near"), 0, NOW);
+        contract.remove_ft_token_id(account("near"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token usdc.near is not accepted by the marketplace")]
+    fn sales_cannot_be_listed_in_a_token_that_is_not_accepted() {
+        let mut contract = new_market();
+
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "usdc.near": "1000" }, "is_auction": false }), NOW);
+    }
+
+    #[test]
+    fn sales_in_a_removed_token_can_be_priced_in_another_currency() {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(usdc());
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "usdc.near": "1000" }, "is_auction": false }), NOW);
+        call(account("owner.near"), 0, NOW);
+        contract.remove_ft_token_id(usdc());
+
+        call(alice(), 1, NOW);
+        contract.update_price(nft(), "1".to_string(), Some(account("near")), U128(ONE_NEAR));
+
+        assert_eq!(sale(&contract, "1").unwrap().sale_conditions.get(&account("near")), Some(&U128(ONE_NEAR)));
+    }
+}
@@ -1,4 +1,13 @@
 use crate::*;
+use crate::nft_callbacks::SaleArgs;
+use crate::events::log_event;
+use near_sdk::log;
+use near_sdk::serde_json::json;
+
+//timestamps before this (September 2001) are almost certainly in seconds or milliseconds instead of nanoseconds
+const MIN_TIMESTAMP: u64 = 1_000_000_000_000_000_000;
+//shortest duration accepted in the sale args. Anything shorter is almost certainly not in nanoseconds
+const MIN_DURATION: u64 = 1_000_000_000; // 1 second
 
 pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     let mut hash = CryptoHash::default();
@@ -6,6 +15,77 @@ pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     hash
 }
 
+//hashes a sale ID (contract + DELIMITER + token ID) to get a unique prefix for collections that belong to a sale
+pub(crate) fn hash_sale_id(contract_and_token_id: &str) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(contract_and_token_id.as_bytes()));
+    hash
+}
+
+//hashes a sale ID and the sequence number of one of its auctions to get a unique prefix for collections that belong to that auction
+pub(crate) fn hash_auction_id(contract_and_token_id: &str, auction_seq: u64) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(format!("{}{}{}", contract_and_token_id, DELIMETER, auction_seq).as_bytes()));
+    hash
+}
+
+//makes sure a timestamp passed in by a user is a Unix timestamp in nanoseconds
+pub(crate) fn assert_timestamp_in_nanoseconds(name: &str, timestamp: Option<U64>) {
+    if let Some(timestamp) = timestamp {
+        assert!(
+            timestamp.0 >= MIN_TIMESTAMP,
+            "{} must be a Unix timestamp in nanoseconds, got {}",
+            name,
+            timestamp.0
+        );
+    }
+}
+
+//makes sure a duration from the sale args is in nanoseconds
+fn assert_duration_in_nanoseconds(name: &str, duration: Option<U64>) {
+    if let Some(duration) = duration {
+        assert!(
+            duration.0 >= MIN_DURATION,
+            "{} must be in nanoseconds and at least {} (1 second), got {}",
+            name,
+            MIN_DURATION,
+            duration.0
+        );
+    }
+}
+
+//returns the fungible token ID that stands for NEAR in sales
+pub(crate) fn near_token_id() -> FungibleTokenId {
+    NEAR_TOKEN_ID.parse().unwrap()
+}
+
+//pays an account in the currency of a sale. NEAR is sent with a plain transfer while
+//fungible tokens are sent by calling ft_transfer on the token contract. If the payment
+//fails, resolve_payment credits the amount to the account's pending balance instead
+pub(crate) fn pay_account(ft_token_id: &FungibleTokenId, receiver_id: AccountId, amount: u128) -> Promise {
+    let payment = if ft_token_id.as_str() == NEAR_TOKEN_ID {
+        Promise::new(receiver_id.clone()).transfer(amount)
+    } else {
+        ext_ft::ft_transfer(
+            receiver_id.clone(),
+            U128(amount),
+            Some("payout from market".to_string()),
+            ft_token_id.clone(), //contract to initiate the cross contract call to
+            1, //yoctoNEAR to attach to the call
+            GAS_FOR_FT_TRANSFER, //GAS to attach to the call
+        )
+    };
+
+    payment.then(ext_self::resolve_payment(
+        receiver_id,
+        ft_token_id.clone(),
+        U128(amount),
+        env::current_account_id(), //we are invoking this function on the current contract
+        NO_DEPOSIT, //don't attach any deposit
+        GAS_FOR_RESOLVE_PAYMENT, //GAS attached to the call to credit the pending balance
+    ))
+}
+
 impl Contract {
     //internal method for removing a sale from the market. This returns the previously removed sale object
     pub(crate) fn internal_remove_sale(
@@ -18,6 +98,8 @@ impl Contract {
         let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
         //get the sale object by removing the unique sale ID. If there was no sale, panic
         let sale = self.sales.remove(&contract_and_token_id).expect("No sale");
+
+        self.internal_unindex_auction(&contract_and_token_id, &sale);
         
         //get the set of sales for the sale's owner. If there's no sale, panic. 
         let mut by_owner_id = self.by_owner_id.get(&sale.owner_id).expect("No sale by_owner_id");
@@ -54,99 +136,762 @@ impl Contract {
         sale
     }
     
+    //internal method for placing a bid on an auction. The amount is in the token the auction is priced in
+    //and is already held by the market, so the previous highest bidder gets refunded here.
+    //for proxy bids the amount is the bidder's maximum and the market bids for them up to it, one increment at a time
+    pub(crate) fn internal_add_bid(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        ft_token_id: FungibleTokenId,
+        bidder_id: AccountId,
+        amount: u128,
+        is_proxy: bool,
+    ) {
+        //the bid history is kept for good, so the bidder pays for the storage it uses out of their storage deposit
+        let initial_storage_usage = env::storage_usage();
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
+
+        if sale.is_auction {
+            let current_time: u64 = env::block_timestamp();
+            let start_time=sale.start_time;
+            let end_time=sale.end_time;
+
+            assert!( start_time.unwrap() < current_time, "Cannot bid before auction starts");
+            assert!( current_time < end_time.unwrap() ,"Cannot bid since auction is over" );
+        }
+        else{
+            env::panic_str("Sale should be an auction");
+        }
+
+        assert!(!sale.is_sealed_bid, "Please use commit_bid function to bid on this sealed bid auction!");
+
+        assert_ne!(sale.owner_id, bidder_id, "Cannot bid on your own sale.");
+
+        //make sure the bid is in the token the auction is priced in
+        let (auction_ft_token_id, starting_price) = sale.auction_condition();
+        assert_eq!(
+            auction_ft_token_id,
+            ft_token_id,
+            "This auction is priced in {}",
+            auction_ft_token_id
+        );
+
+        //the lowest amount this bid can be
+        let min_amount = match sale.bids.as_ref().and_then(|bids| bids.last()) {
+            Some(current_bid) => {
+                let min_bid_increment = sale.min_bid_increment_on(current_bid.price.0);
+
+                assert!(
+                    amount >= (current_bid.price.0 + min_bid_increment),
+                    "Can't pay less than or equal to current bid price + increment ({}) : {:?}",
+                    min_bid_increment,
+                    current_bid.price
+                );
+
+                assert!(
+                    amount > starting_price,
+                    "Can't pay less than or equal to starting price: {:?}",
+                    U128(starting_price)
+                );
+
+                current_bid.price.0 + min_bid_increment
+            }
+            None => {
+                let min_bid_increment = sale.min_bid_increment_on(starting_price);
+
+                assert!(
+                    amount >= (starting_price + min_bid_increment),
+                    "Can't pay less than or equal to starting price + increment ({}): {}",
+                    min_bid_increment,
+                    starting_price
+                );
+
+                starting_price + min_bid_increment
+            }
+        };
+
+        let current_time = U64(env::block_timestamp());
+        let current_escrow = sale.current_bid_escrow();
+        // the sale only keeps the current bid, every bid is kept in the bid history
+        let current_bid = sale.bids.take().unwrap_or_default().pop();
+
+        match (current_bid, current_escrow) {
+            //the current bidder's maximum beats the new bid, so the new bid is refunded and the market
+            //bids for the current bidder one increment above it (or up to their maximum)
+            (Some(current_bid), Some((_, current_max))) if current_bid.bidder_id != bidder_id && current_max >= amount => {
+                self.internal_record_bid(&contract_and_token_id, sale.auction_seq, &Bid {
+                    bidder_id: bidder_id.clone(),
+                    price: U128(amount),
+                    timestamp: current_time,
+                });
+                pay_account(&ft_token_id, bidder_id.clone(), amount);
+                sale.runner_up_bid = Some(sale.runner_up_bid.unwrap_or(0).max(amount));
+
+                let price = sale.proxy_bid_price(amount + sale.min_bid_increment_on(amount), current_max);
+                let proxy_bid = Bid {
+                    bidder_id: current_bid.bidder_id,
+                    price: U128(price),
+                    timestamp: current_time,
+                };
+                self.internal_record_bid(&contract_and_token_id, sale.auction_seq, &proxy_bid);
+                sale.bids = Some(vec![proxy_bid]);
+            }
+            //the new bid wins. The current bidder gets back everything the market holds for them
+            (current_bid, current_escrow) => {
+                let mut price = amount;
+                if is_proxy {
+                    //a proxy bid only bids what it takes to beat the current bidder's maximum. If the bidder is
+                    //raising their own maximum, their bid stays where it is
+                    price = match (&current_bid, &current_escrow) {
+                        (Some(current_bid), _) if current_bid.bidder_id == bidder_id => current_bid.price.0,
+                        (_, Some((_, current_max))) => min_amount.max(current_max + sale.min_bid_increment_on(*current_max)),
+                        _ => min_amount,
+                    };
+                    price = sale.proxy_bid_price(price, amount);
+                }
+
+                if let Some((current_bidder_id, current_escrow)) = current_escrow {
+                    //the outbid bidder is now the runner up
+                    if current_bidder_id != bidder_id {
+                        sale.runner_up_bid = Some(sale.runner_up_bid.unwrap_or(0).max(current_escrow));
+                    }
+                    // refund
+                    pay_account(&ft_token_id, current_bidder_id, current_escrow);
+                }
+
+                let new_bid = Bid {
+                    bidder_id: bidder_id.clone(),
+                    price: U128(price),
+                    timestamp: current_time,
+                };
+                self.internal_record_bid(&contract_and_token_id, sale.auction_seq, &new_bid);
+                sale.bids = Some(vec![new_bid]);
+                sale.max_bid = if is_proxy { Some(amount) } else { None };
+            }
+        }
+
+        //if the bid came in during the extension window, push the end time forward so other bidders have time to respond
+        if let (Some(extension_window), Some(extension_duration)) = (sale.extension_window, sale.extension_duration) {
+            let end_time = sale.end_time.unwrap();
+            let current_time: u64 = env::block_timestamp();
+
+            if current_time + extension_window >= end_time {
+                let mut new_end_time = end_time + extension_duration;
+                //never go past the hard cap, if there is one
+                if let Some(max_end_time) = sale.max_end_time {
+                    new_end_time = new_end_time.min(max_end_time);
+                }
+                self.internal_unindex_auction(&contract_and_token_id, &sale);
+                sale.end_time = Some(new_end_time);
+                self.internal_index_auction(&contract_and_token_id, &sale);
+            }
+        }
+
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_charge_storage(&bidder_id, initial_storage_usage);
+    }
+
+    //internal method for buying a live auction at its buy now price. The amount is in the token the auction is
+    //priced in and is already held by the market. The current highest bidder and anything paid above the buy now
+    //price are refunded and the auction is settled.
+    pub(crate) fn internal_buy_now(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        ft_token_id: FungibleTokenId,
+        buyer_id: AccountId,
+        amount: u128,
+    ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+
+        assert!(sale.is_auction, "Sale should be an auction");
+        assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
+
+        let current_time: u64 = env::block_timestamp();
+        assert!( sale.start_time.unwrap() < current_time, "Cannot buy before auction starts");
+        assert!( current_time < sale.end_time.unwrap() ,"Cannot buy since auction is over" );
+
+        //make sure the payment is in the token the auction is priced in
+        let (auction_ft_token_id, _) = sale.auction_condition();
+        assert_eq!(
+            auction_ft_token_id,
+            ft_token_id,
+            "This auction is priced in {}",
+            auction_ft_token_id
+        );
+
+        assert!(
+            sale.is_buy_now_available(),
+            "Buy now is not available for this auction. Please use add_bid function to bid on this auction item!"
+        );
+
+        let buy_now_price = sale.buy_now_price.unwrap();
+        assert!(amount >= buy_now_price, "Amount must be greater than or equal to the buy now price: {:?}", buy_now_price);
+
+        //refund the current highest bidder since their bid won't win anymore
+        if let Some((bidder_id, escrow)) = sale.current_bid_escrow() {
+            pay_account(&ft_token_id, bidder_id, escrow);
+        }
+
+        //refund whatever was paid above the buy now price
+        if amount > buy_now_price {
+            pay_account(&ft_token_id, buyer_id.clone(), amount - buy_now_price);
+        }
+
+        //process the purchase (which will remove the auction, transfer and get the payout from the nft contract, and then distribute royalties)
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            ft_token_id,
+            U128(buy_now_price),
+            buyer_id,
+            None,
+        )
+    }
+
+    //internal method for buying a dutch auction at its current price. The amount is in the token the auction is
+    //priced in and is already held by the market, so anything paid above the current price is refunded here
+    pub(crate) fn internal_buy_dutch_auction(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        ft_token_id: FungibleTokenId,
+        buyer_id: AccountId,
+        amount: u128,
+    ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+
+        assert!(sale.is_dutch_auction, "Sale should be a dutch auction");
+        assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
+
+        let current_time: u64 = env::block_timestamp();
+        assert!( sale.start_time.unwrap() <= current_time, "Cannot buy before auction starts");
+        assert!( current_time < sale.end_time.unwrap() ,"Cannot buy since auction is over" );
+
+        //make sure the payment is in the token the auction is priced in
+        let (auction_ft_token_id, _) = sale.auction_condition();
+        assert_eq!(
+            auction_ft_token_id,
+            ft_token_id,
+            "This auction is priced in {}",
+            auction_ft_token_id
+        );
+
+        let price = sale.dutch_auction_price_at(current_time);
+        assert!(amount >= price, "Amount must be greater than or equal to the current price: {:?}", price);
+
+        //refund whatever was paid above the current price
+        if amount > price {
+            pay_account(&ft_token_id, buyer_id.clone(), amount - price);
+        }
+
+        //process the purchase (which will remove the auction, transfer and get the payout from the nft contract, and then distribute royalties)
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            ft_token_id,
+            U128(price),
+            buyer_id,
+            None,
+        )
+    }
+
+    //internal method for ending an auction once it's over. If the auction was settled by a keeper,
+    //the keeper is paid the keeper fee out of the marketplace's fees
+    pub(crate) fn internal_end_auction(&mut self, nft_contract_id: AccountId, token_id: TokenId, keeper_id: Option<AccountId>) {
+        let contract_id: AccountId = nft_contract_id;
+        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
+        assert!(!sale.is_ended, "Auction has already ended. Relist it with nft_approve or remove it");
+
+        //sealed bid auctions end after the reveal phase and settle to the highest revealed bid
+        if sale.is_sealed_bid {
+            let current_time: u64 = env::block_timestamp();
+            assert!( Some(current_time) > sale.reveal_end_time, "Cannot end before reveal_end_time mentioned for the auction");
+
+            self.internal_settle_sealed_bid_auction(sale, keeper_id);
+            return;
+        }
+
+        if sale.is_auction {
+            let current_time: u64 = env::block_timestamp();
+            let end_time=sale.end_time;
+
+            assert!( Some(current_time) > end_time, "Cannot end before end_time mentioned for the auction");
+        }
+        else{
+            env::panic_str("Sale should be an auction");
+        }
+
+        let (ft_token_id, _) = sale.auction_condition();
+        let reserve_met = sale.is_reserve_met();
+        let keep_if_reserve_not_met = sale.keep_if_reserve_not_met;
+        let bids = sale.bids.clone().unwrap_or_default();
+        let current_escrow = sale.current_bid_escrow();
+
+        if !bids.is_empty() && !reserve_met {
+            //the highest bid didn't reach the reserve price so the bidder gets their money back
+            let (bidder_id, escrow) = current_escrow.unwrap();
+            pay_account(&ft_token_id, bidder_id, escrow);
+            log!("Reserve price not met for {}", contract_and_token_id);
+
+            if keep_if_reserve_not_met {
+                //leave the auction on the market without bids so the seller can relist it with nft_approve.
+                //it no longer needs to be settled so it comes out of the end time index
+                let mut sale = sale;
+                sale.is_ended = true;
+                sale.bids = Some(Vec::new());
+                sale.max_bid = None;
+                sale.runner_up_bid = None;
+                self.internal_unindex_auction(&contract_and_token_id, &sale);
+                self.sales.insert(&contract_and_token_id, &sale);
+            } else {
+                self.internal_remove_sale(contract_id, token_id);
+            }
+        }
+        else if !bids.is_empty() {
+            let current_bid = &bids[bids.len() - 1];
+            let buyer_id= current_bid.bidder_id.clone();
+
+            //the winner only pays the winning price. The rest of a proxy bidder's maximum, or the difference
+            //to the second price, is refunded
+            let price = sale.winning_price(current_bid.price.0, sale.runner_up_bid);
+            let (_, escrow) = current_escrow.unwrap();
+            if escrow > price {
+                pay_account(&ft_token_id, buyer_id.clone(), escrow - price);
+            }
+
+            //the auction settles in the token it was priced in
+            self.process_purchase(
+                contract_id,
+                token_id,
+                ft_token_id,
+                U128(price),
+                buyer_id,
+                keeper_id,
+            );
+        }
+        else{
+            self.internal_relist_or_remove(sale);
+        }
+    }
+
+    //internal method for an auction that ended without bids. It is relisted if it has relists left, turned into
+    //a fixed price sale if the seller asked for that, and removed from the market otherwise
+    pub(crate) fn internal_relist_or_remove(&mut self, mut sale: Sale) {
+        let nft_contract_id: AccountId = sale.nft_contract_id.parse().unwrap();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, sale.token_id);
+        let (ft_token_id, starting_price) = sale.auction_condition();
+
+        if sale.relists_left > 0 {
+            self.internal_unindex_auction(&contract_and_token_id, &sale);
+
+            //the relisted auction starts now and every other deadline moves along with the end time
+            let current_time: u64 = env::block_timestamp();
+            let old_end_time = sale.end_time.unwrap();
+            let duration = sale.relist_duration.unwrap_or(old_end_time - sale.start_time.unwrap());
+            let new_end_time = current_time + duration;
+            sale.max_end_time = sale.max_end_time.map(|max_end_time| new_end_time + (max_end_time - old_end_time));
+            sale.reveal_end_time = sale.reveal_end_time.map(|reveal_end_time| new_end_time + (reveal_end_time - old_end_time));
+            sale.start_time = Some(current_time);
+            sale.end_time = Some(new_end_time);
+
+            let starting_price = sale.relist_starting_price.unwrap_or(starting_price);
+            sale.sale_conditions.insert(ft_token_id.clone(), U128(starting_price));
+            sale.bids = Some(Vec::new());
+            sale.max_bid = None;
+            sale.runner_up_bid = None;
+            sale.relists_left -= 1;
+
+            self.internal_index_auction(&contract_and_token_id, &sale);
+            self.sales.insert(&contract_and_token_id, &sale);
+            log_event(
+                "auction_relisted",
+                json!({
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": sale.token_id,
+                    "start_time": U64(current_time),
+                    "end_time": U64(new_end_time),
+                    "ft_token_id": ft_token_id,
+                    "starting_price": U128(starting_price),
+                    "relists_left": sale.relists_left,
+                }),
+            );
+        } else if let Some(relist_fixed_price) = sale.relist_fixed_price {
+            self.internal_unindex_auction(&contract_and_token_id, &sale);
+
+            //the sale stays on the market with a single price in the auction's token. Everything else that only
+            //applies to auctions (deadlines, reserve, buy now, increments, sealed and dutch settings) is dropped
+            let sale = Sale::new_fixed_price(
+                sale.owner_id,
+                sale.approval_id,
+                nft_contract_id.clone(),
+                sale.token_id,
+                ft_token_id.clone(),
+                relist_fixed_price,
+            );
+
+            self.sales.insert(&contract_and_token_id, &sale);
+            log_event(
+                "auction_converted_to_fixed_price",
+                json!({
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": sale.token_id,
+                    "ft_token_id": ft_token_id,
+                    "price": U128(relist_fixed_price),
+                }),
+            );
+        } else {
+            self.internal_remove_sale(nft_contract_id, sale.token_id);
+        }
+    }
+
+    //internal method for selling a token that is no longer on the market (or never was) to a buyer whose payment the
+    //market already holds. This transfers the token with nft_transfer_payout and pays out royalties and fees in resolve_purchase
+    pub(crate) fn internal_transfer_and_pay(
+        &mut self,
+        sale: Sale,
+        ft_token_id: FungibleTokenId,
+        price: U128,
+        buyer_id: AccountId,
+        keeper_id: Option<AccountId>,
+    ) -> Promise {
+        let nft_contract_id: AccountId = sale.nft_contract_id.parse().unwrap();
+
+        //initiate a cross contract call to the nft contract. This will transfer the token to the buyer and return
+        //a payout object used for the market to distribute funds to the appropriate accounts.
+        ext_contract::nft_transfer_payout(
+            buyer_id.clone(), //purchaser (person to transfer the NFT to)
+            sale.token_id.clone(), //token ID to transfer
+            sale.approval_id, //market contract's approval ID in order to transfer the token on behalf of the owner
+            "payout from market".to_string(), //memo (to include some context)
+            /*
+                the price that the token was purchased for. This will be used in conjunction with the royalty percentages
+                for the token in order to determine how much money should go to which account. 
+            */
+            price,
+            10, //the maximum amount of accounts the market can payout at once (this is limited by GAS)
+            nft_contract_id, //contract to initiate the cross contract call to
+            1, //yoctoNEAR to attach to the call
+            GAS_FOR_NFT_TRANSFER, //GAS to attach to the call
+        )
+        //after the transfer payout has been initiated, we resolve the promise by calling our own resolve_purchase function. 
+        //resolve purchase will take the payout object returned from the nft_transfer_payout and actually pay the accounts
+        .then(ext_self::resolve_purchase(
+            buyer_id, //the buyer, currency and price are passed in incase something goes wrong and we need to refund the buyer
+            ft_token_id,
+            price,
+            sale,
+            keeper_id, //the keeper that settled the auction (if any) gets part of the fees
+            env::current_account_id(), //we are invoking this function on the current contract
+            NO_DEPOSIT, //don't attach any deposit
+            GAS_FOR_ROYALTIES, //GAS attached to the call to payout royalties
+        ))
+    }
+
     pub(crate) fn internal_add_market_data(
         &mut self,
         owner_id: AccountId,
         approval_id: u64,
         nft_contract_id: AccountId,
         token_id: TokenId,
-        price: U128,
-        start_time: Option<U64>,
-        end_time: Option<U64>,
-        is_auction: bool,
+        sale_args: SaleArgs,
     ) {
+        let SaleArgs {
+            sale_conditions,
+            is_auction,
+            start_time,
+            end_time,
+            extension_window,
+            extension_duration,
+            max_end_time,
+            reserve_price,
+            hide_reserve_price,
+            keep_if_reserve_not_met,
+            buy_now_price,
+            buy_now_bid_threshold,
+            is_dutch_auction,
+            floor_price,
+            price_step_interval,
+            is_sealed_bid,
+            reveal_end_time,
+            forfeit_penalty,
+            min_bid_increment,
+            min_bid_increment_bps,
+            is_second_price,
+            relist_count,
+            relist_duration,
+            relist_starting_price,
+            relist_fixed_price,
+            duration,
+            start_delay,
+        } = sale_args;
 
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
 
-        let bids: Option<Bids> = match is_auction {
-            u => {
-                if u {
-                    Some(Vec::new())
-                } else {
-                    None
-                }
-            }
+        let bids: Option<Bids> = if is_auction {
+            Some(Vec::new())
+        } else {
+            None
         };
 
-        // Time checks
+        // Time checks. All timestamps are Unix timestamps in nanoseconds and all durations are in nanoseconds
         let current_time: u64 = env::block_timestamp();
 
-        if start_time.is_some() {
-            assert!(start_time.unwrap().0 >= current_time);
+        assert_timestamp_in_nanoseconds("start_time", start_time);
+        assert_timestamp_in_nanoseconds("end_time", end_time);
+        assert_timestamp_in_nanoseconds("max_end_time", max_end_time);
+        assert_timestamp_in_nanoseconds("reveal_end_time", reveal_end_time);
+        assert_duration_in_nanoseconds("duration", duration);
+        assert_duration_in_nanoseconds("start_delay", start_delay);
+        assert_duration_in_nanoseconds("extension_window", extension_window);
+        assert_duration_in_nanoseconds("extension_duration", extension_duration);
+        assert_duration_in_nanoseconds("price_step_interval", price_step_interval);
 
-            if end_time.is_some() {
-                assert!(start_time.unwrap().0 < end_time.unwrap().0);
+        // A duration (and optional start delay) is resolved against the time the approval lands on chain
+        let (start_time, end_time) = match duration {
+            Some(duration) => {
+                assert!(
+                    start_time.is_none() && end_time.is_none(),
+                    "Use either start_time and end_time or duration, not both"
+                );
+                let start_time = current_time + start_delay.map(|x| x.0).unwrap_or(0);
+                (Some(U64(start_time)), Some(U64(start_time + duration.0)))
+            }
+            None => {
+                assert!(start_delay.is_none(), "start_delay can only be used with duration");
+                (start_time, end_time)
+            }
+        };
+
+        if let Some(start_time) = start_time {
+            assert!(
+                start_time.0 >= current_time,
+                "start_time ({}) has already passed, the current time is {}. Consider using duration instead",
+                start_time.0,
+                current_time
+            );
+
+            if let Some(end_time) = end_time {
+                assert!(start_time.0 < end_time.0, "end_time must be after start_time");
             }
         }
 
-        if end_time.is_some() {
-            assert!(end_time.unwrap().0 >= current_time);
+        if let Some(end_time) = end_time {
+            assert!(
+                end_time.0 >= current_time,
+                "end_time ({}) has already passed, the current time is {}",
+                end_time.0,
+                current_time
+            );
         }
 
         // Making sure that start time and endtime is provided if its an auction
         if is_auction{
-            assert!(start_time.is_some(), "Start time is not provided.");
-            assert!(end_time.is_some(), "End time is not provided.")
-        }
-
-        // Trying to put in the old price and old bids, if anyone tries to approve again.
-        let mut auction_exists: bool = false;
-        let mut old_price: u128=0;
-        let mut old_bids: Option<Bids>=None;
-        let mut old_start_time: Option<u64>=None;
-        let mut old_end_time: Option<u64>=None;
-
-        if self.sales.get(&contract_and_token_id).is_some() && is_auction{
-            auction_exists=true;
-            let sale=self.sales.get(&contract_and_token_id).unwrap();
-            old_price=sale.price;
-            old_bids=sale.bids;
-            old_start_time=sale.start_time;
-            old_end_time=sale.end_time;
-        }
-
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: owner_id.clone().into(),
-                approval_id,
-                nft_contract_id: nft_contract_id.clone().into(),
-                token_id: token_id.clone(),
-                price: match auction_exists{
-                    true=>old_price,
-                    false=>price.into(),
-                },
-                bids: match auction_exists{
-                    true=>old_bids,
-                    false=>bids,
-                },
-                start_time: match auction_exists{
-                    true=>old_start_time,
-                    false=> match start_time {
-                                Some(x) => Some(x.0),
-                                None => None,
-                            }
-                },
-                end_time: match auction_exists{
-                    true=>old_end_time,
-                    false=> match end_time {
-                                Some(x) => Some(x.0),
-                                None => None,
-                            }
-                },
-                is_auction: is_auction,
-            },
+            assert!(start_time.is_some(), "Start time is not provided. Pass start_time and end_time, or duration.");
+            assert!(end_time.is_some(), "End time is not provided. Pass start_time and end_time, or duration.");
+            assert_eq!(sale_conditions.len(), 1, "Auctions can only be priced in one token");
+        }
+
+        // Dutch auction checks. The price drops from the single sale condition to the floor between the start and end time
+        if is_dutch_auction {
+            assert!(!is_auction, "A sale cannot be both an auction and a dutch auction");
+            assert!(start_time.is_some(), "Start time is not provided. Pass start_time and end_time, or duration.");
+            assert!(end_time.is_some(), "End time is not provided. Pass start_time and end_time, or duration.");
+            assert_eq!(sale_conditions.len(), 1, "Dutch auctions can only be priced in one token");
+
+            let floor_price = floor_price.expect("Floor price is not provided.");
+            assert!(
+                floor_price.0 < sale_conditions.values().next().unwrap().0,
+                "Floor price must be less than the starting price"
+            );
+            if let Some(price_step_interval) = price_step_interval {
+                assert!(price_step_interval.0 > 0, "Price step interval must be greater than 0");
+            }
+        } else {
+            assert!(
+                floor_price.is_none() && price_step_interval.is_none(),
+                "Only dutch auctions can have a floor price or a price step interval"
+            );
+        }
+
+        // Anti-sniping checks. The window and duration go together and the hard cap can't be before the end time
+        assert_eq!(
+            extension_window.is_some(),
+            extension_duration.is_some(),
+            "Extension window and extension duration must be provided together"
         );
+        if extension_window.is_some() || max_end_time.is_some() {
+            assert!(is_auction, "Only auctions can be extended");
+        }
+        if let Some(max_end_time) = max_end_time {
+            assert!(extension_window.is_some(), "Max end time needs an extension window");
+            assert!(max_end_time.0 >= end_time.unwrap().0, "Max end time cannot be before the end time");
+        }
+
+        // Reserve checks. The reserve is only for auctions and can't be below the starting price
+        if let Some(reserve_price) = reserve_price {
+            assert!(is_auction, "Only auctions can have a reserve price");
+            assert!(
+                reserve_price.0 >= sale_conditions.values().next().unwrap().0,
+                "Reserve price cannot be less than the starting price"
+            );
+        }
+
+        // Buy now checks. The buy now price has to be above the starting price and the reserve, and the threshold below it
+        if let Some(buy_now_price) = buy_now_price {
+            assert!(is_auction, "Only auctions can have a buy now price");
+            assert!(
+                buy_now_price.0 > sale_conditions.values().next().unwrap().0,
+                "Buy now price must be greater than the starting price"
+            );
+            assert!(
+                buy_now_price.0 >= reserve_price.map(|x| x.0).unwrap_or(0),
+                "Buy now price cannot be less than the reserve price"
+            );
+        }
+        if let Some(buy_now_bid_threshold) = buy_now_bid_threshold {
+            let buy_now_price = buy_now_price.expect("Buy now bid threshold needs a buy now price");
+            assert!(
+                buy_now_bid_threshold.0 <= buy_now_price.0,
+                "Buy now bid threshold cannot be greater than the buy now price"
+            );
+        }
+
+        // Bid increment checks. Sellers pick either an absolute increment or a percentage of the current bid, and either one
+        // has to be within the owner's bounds (an absolute increment is compared to the starting price)
+        assert!(
+            min_bid_increment.is_none() || min_bid_increment_bps.is_none(),
+            "Cannot have both an absolute and a percentage minimum bid increment"
+        );
+        if min_bid_increment.is_some() || min_bid_increment_bps.is_some() {
+            assert!(is_auction, "Only auctions can have a minimum bid increment");
+        }
+        if let Some(min_bid_increment_bps) = min_bid_increment_bps {
+            assert!(
+                min_bid_increment_bps >= self.min_bid_increment_bps && min_bid_increment_bps <= self.max_bid_increment_bps,
+                "Minimum bid increment must be between {} and {} basis points",
+                self.min_bid_increment_bps,
+                self.max_bid_increment_bps
+            );
+        }
+        if let Some(min_bid_increment) = min_bid_increment {
+            let starting_price = sale_conditions.values().next().unwrap().0;
+            assert!(
+                min_bid_increment.0 >= starting_price * self.min_bid_increment_bps / 10_000
+                    && min_bid_increment.0 <= starting_price * self.max_bid_increment_bps / 10_000,
+                "Minimum bid increment must be between {} and {} basis points of the starting price",
+                self.min_bid_increment_bps,
+                self.max_bid_increment_bps
+            );
+            assert!(min_bid_increment.0 > 0, "Minimum bid increment must be greater than 0");
+        }
+
+        // Sealed bid checks. Bids are committed until end_time and revealed until reveal_end_time
+        if is_sealed_bid {
+            assert!(is_auction, "Only auctions can be sealed bid auctions");
+            let reveal_end_time = reveal_end_time.expect("Reveal end time is not provided.");
+            assert!(reveal_end_time.0 > end_time.unwrap().0, "Reveal end time must be after the end time");
+            assert!(
+                extension_window.is_none() && buy_now_price.is_none(),
+                "Sealed bid auctions cannot be extended or bought now"
+            );
+        } else {
+            assert!(
+                reveal_end_time.is_none() && forfeit_penalty.is_none(),
+                "Only sealed bid auctions can have a reveal end time or a forfeit penalty"
+            );
+        }
+
+        // Second price auctions need bids to compare, so they have to be auctions
+        assert!(!is_second_price || is_auction, "Only auctions can be second price auctions");
+
+        // Relist checks. Auctions that end without bids can be relisted, optionally cheaper, and then turned into a fixed price sale
+        if is_auction {
+            assert_duration_in_nanoseconds("relist_duration", relist_duration);
+            if let Some(relist_starting_price) = relist_starting_price {
+                let starting_price = sale_conditions.values().next().unwrap().0;
+                assert!(
+                    relist_starting_price.0 > 0 && relist_starting_price.0 <= starting_price,
+                    "Relist starting price must be greater than 0 and at most the starting price"
+                );
+            }
+            if let Some(relist_fixed_price) = relist_fixed_price {
+                assert!(relist_fixed_price.0 > 0, "Relist fixed price must be greater than 0");
+            }
+        } else {
+            assert!(
+                relist_count == 0 && relist_duration.is_none() && relist_starting_price.is_none() && relist_fixed_price.is_none(),
+                "Only auctions can be relisted"
+            );
+        }
+
+        // Trying to put in the old auction with its price and bids, if anyone tries to approve again.
+        // An auction that already ended without bids (because its reserve wasn't met) is replaced so the seller can relist it,
+        // and so is a fixed price sale that is approved again as an auction.
+        let has_sealed_bids = self.has_sealed_bids(&contract_and_token_id);
+        //drop the old sale from the end time index. The sale is indexed again once it's stored
+        if let Some(old_sale) = self.sales.get(&contract_and_token_id) {
+            self.internal_unindex_auction(&contract_and_token_id, &old_sale);
+        }
+        let sale = match self.sales.get(&contract_and_token_id) {
+            Some(mut old_sale) if old_sale.is_auction && is_auction && (!old_sale.is_ended_without_bids() || has_sealed_bids) => {
+                old_sale.owner_id = owner_id.clone();
+                old_sale.approval_id = approval_id;
+                old_sale.is_auction = is_auction;
+                old_sale
+            }
+            _ => {
+                //every new auction gets the next sequence number for the token, so it starts with a fresh bid history
+                //while the history of earlier auctions is kept
+                let auction_seq = if is_auction { self.internal_next_auction_seq(&contract_and_token_id) } else { 0 };
+                Sale {
+                    owner_id: owner_id.clone(),
+                    approval_id,
+                    nft_contract_id: nft_contract_id.clone().into(),
+                    token_id: token_id.clone(),
+                    sale_conditions,
+                    bids,
+                    is_auction,
+                    start_time: start_time.map(|x| x.0),
+                    end_time: end_time.map(|x| x.0),
+                    extension_window: extension_window.map(|x| x.0),
+                    extension_duration: extension_duration.map(|x| x.0),
+                    max_end_time: max_end_time.map(|x| x.0),
+                    reserve_price: reserve_price.map(|x| x.0),
+                    hide_reserve_price,
+                    keep_if_reserve_not_met,
+                    is_ended: false,
+                    buy_now_price: buy_now_price.map(|x| x.0),
+                    buy_now_bid_threshold: buy_now_bid_threshold.map(|x| x.0),
+                    is_dutch_auction,
+                    floor_price: floor_price.map(|x| x.0),
+                    price_step_interval: price_step_interval.map(|x| x.0),
+                    is_sealed_bid,
+                    reveal_end_time: reveal_end_time.map(|x| x.0),
+                    forfeit_penalty: forfeit_penalty.map(|x| x.0),
+                    min_bid_increment: min_bid_increment.map(|x| x.0),
+                    min_bid_increment_bps,
+                    max_bid: None,
+                    is_second_price,
+                    runner_up_bid: None,
+                    relists_left: relist_count,
+                    relist_duration: relist_duration.map(|x| x.0),
+                    relist_starting_price: relist_starting_price.map(|x| x.0),
+                    relist_fixed_price: relist_fixed_price.map(|x| x.0),
+                    auction_seq,
+                }
+            }
+        };
+
+        self.sales.insert(&contract_and_token_id, &sale);
+        //add auctions to the end time index so they can be settled once they're over
+        self.internal_index_auction(&contract_and_token_id, &sale);
         //get the sales by owner ID for the given owner. If there are none, we create a new empty set
         let mut by_owner_id = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
             UnorderedSet::new(
@@ -185,4 +930,439 @@ impl Contract {
         self.by_nft_contract_id
             .insert(&nft_contract_id, &by_nft_contract_id);
     }
+
+    //how many offers and buy orders the account has open. Each one takes up one sale worth of storage deposit
+    pub(crate) fn internal_offer_count(&self, account_id: &AccountId) -> u64 {
+        let collection_offers = self.collection_offers_by_buyer_id.get(account_id).map(|by_buyer_id| by_buyer_id.len()).unwrap_or(0);
+        let token_offers = self.token_offers_by_buyer_id.get(account_id).map(|by_buyer_id| by_buyer_id.len()).unwrap_or(0);
+        let buy_orders = self.buy_orders_by_buyer_id.get(account_id).map(|by_buyer_id| by_buyer_id.len()).unwrap_or(0);
+        collection_offers + token_offers + buy_orders
+    }
+
+    //internal method for taking the cost of the storage used since initial_storage_usage out of an account's storage
+    //deposit. It pays for what the account adds to the market's records (the bid history, sealed bid commitments and
+    //negotiation rounds), so the deposit has to cover it on top of the account's sales and offers. Bids in fungible
+    //tokens are charged to the sender's storage deposit as well
+    pub(crate) fn internal_charge_storage(&mut self, account_id: &AccountId, initial_storage_usage: u64) {
+        let storage_cost = u128::from(env::storage_usage().saturating_sub(initial_storage_usage)) * STORAGE_PRICE_PER_BYTE;
+        if storage_cost == 0 {
+            return;
+        }
+
+        let storage_reserved = (self.get_supply_by_owner_id(account_id.clone()).0 + self.internal_offer_count(account_id)) as u128 * STORAGE_PER_SALE;
+        let paid_storage = self.storage_deposits.get(account_id).unwrap_or(0);
+        assert!(
+            paid_storage >= storage_reserved + storage_cost,
+            "Insufficient storage paid: {}, needs {} more on top of {} for sales and offers. Please use storage_deposit",
+            paid_storage, storage_cost, storage_reserved
+        );
+        self.storage_deposits.insert(account_id, &(paid_storage - storage_cost));
+    }
+
+    //makes sure the buyer has paid for the storage of one more offer on top of their sales and open offers
+    pub(crate) fn internal_assert_offer_storage(&self, buyer_id: &AccountId) {
+        let storage_required = (self.get_supply_by_owner_id(buyer_id.clone()).0 + self.internal_offer_count(buyer_id) + 1) as u128 * STORAGE_PER_SALE;
+        let paid_storage = self.storage_deposits.get(buyer_id).unwrap_or(0);
+        assert!(
+            paid_storage >= storage_required,
+            "Insufficient storage paid: {}, for {} sales and offers at {} rate of per sale",
+            paid_storage, storage_required / STORAGE_PER_SALE, STORAGE_PER_SALE
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::{json, Value};
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn bids_close_to_the_end_extend_the_auction_up_to_the_cap() {
+        let mut contract = new_market();
+        let args = json!({
+            "end_time": (NOW + 1000 * SECOND).to_string(),
+            "extension_window": (100 * SECOND).to_string(),
+            "extension_duration": (300 * SECOND).to_string(),
+            "max_end_time": (NOW + 1500 * SECOND).to_string(),
+        });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+
+        //far from the end nothing changes
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 500 * SECOND);
+        assert_eq!(sale(&contract, "1").unwrap().end_time, Some(NOW + 1000 * SECOND));
+
+        //inside the window the end moves forward by the duration
+        bid(&mut contract, carol(), 3 * ONE_NEAR, NOW + 950 * SECOND);
+        assert_eq!(contract.get_auction_end_time(format!("{}.1", nft())), Some(U64(NOW + 1300 * SECOND)));
+
+        //but never past the cap
+        bid(&mut contract, bob(), 4 * ONE_NEAR, NOW + 1250 * SECOND);
+        assert_eq!(sale(&contract, "1").unwrap().end_time, Some(NOW + 1500 * SECOND));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot bid since auction is over")]
+    fn auctions_without_extensions_end_on_time() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({})), NOW);
+
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 999);
+        bid(&mut contract, carol(), 3 * ONE_NEAR, NOW + 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Extension window and extension duration must be provided together")]
+    fn an_extension_window_needs_a_duration() {
+        let mut contract = new_market();
+
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "extension_window": (100 * SECOND).to_string() })), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "Max end time cannot be before the end time")]
+    fn the_cap_cannot_be_before_the_end_time() {
+        let mut contract = new_market();
+        let args = json!({
+            "extension_window": (100 * SECOND).to_string(),
+            "extension_duration": (300 * SECOND).to_string(),
+            "max_end_time": (NOW + 999).to_string(),
+        });
+
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+    }
+
+    #[test]
+    fn buy_now_refunds_the_highest_bidder_and_settles_the_auction() {
+        let mut contract = new_market();
+        let args = json!({ "buy_now_price": (5 * ONE_NEAR).to_string(), "buy_now_bid_threshold": (3 * ONE_NEAR).to_string() });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        call(carol(), 5 * ONE_NEAR, NOW + 2);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 2 * ONE_NEAR)]);
+        let transfer = scheduled_call("nft_transfer_payout");
+        assert_eq!((transfer["receiver_id"].clone(), transfer["balance"].clone()), (json!(carol()), json!((5 * ONE_NEAR).to_string())));
+        assert!(sale(&contract, "1").is_none());
+    }
+
+    #[test]
+    fn buy_now_settles_at_the_buy_now_price_and_refunds_the_rest() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "buy_now_price": (5 * ONE_NEAR).to_string() })), NOW);
+
+        call(carol(), 6 * ONE_NEAR, NOW + 2);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(payments(), vec![("near".to_string(), carol(), ONE_NEAR)]);
+        assert_eq!(scheduled_call("nft_transfer_payout")["balance"], json!((5 * ONE_NEAR).to_string()));
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!((5 * ONE_NEAR).to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Buy now is not available for this auction")]
+    fn buy_now_goes_away_with_the_first_bid_without_a_threshold() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "buy_now_price": (5 * ONE_NEAR).to_string() })), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        call(carol(), 5 * ONE_NEAR, NOW + 2);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Buy now is not available for this auction")]
+    fn buy_now_goes_away_once_the_bids_reach_the_threshold() {
+        let mut contract = new_market();
+        let args = json!({ "buy_now_price": (5 * ONE_NEAR).to_string(), "buy_now_bid_threshold": (3 * ONE_NEAR).to_string() });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+        bid(&mut contract, bob(), 3 * ONE_NEAR, NOW + 1);
+
+        call(carol(), 5 * ONE_NEAR, NOW + 2);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount must be greater than or equal to the buy now price")]
+    fn buy_now_needs_the_full_price() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "buy_now_price": (5 * ONE_NEAR).to_string() })), NOW);
+
+        call(carol(), 4 * ONE_NEAR, NOW + 2);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn sellers_can_set_an_absolute_increment() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment": "100" })), NOW);
+
+        bid(&mut contract, bob(), 1100, NOW + 1);
+        assert_eq!(contract.get_auction_stats(format!("{}.1", nft())).unwrap().min_bid_increment, U128(100));
+        bid(&mut contract, carol(), 1200, NOW + 2);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 1100)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't pay less than or equal to current bid price + increment (110)")]
+    fn percentage_increments_follow_the_current_bid() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment_bps": 1000 })), NOW);
+
+        bid(&mut contract, bob(), 1100, NOW + 1);
+        bid(&mut contract, carol(), 1209, NOW + 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minimum bid increment must be between 0 and 5000 basis points")]
+    fn increments_have_to_be_within_the_owner_bounds() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment_bps": 5001 })), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minimum bid increment must be between 100 and 5000 basis points of the starting price")]
+    fn absolute_increments_are_checked_against_the_starting_price() {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.change_bid_increment_bounds(100, 5000);
+        assert_eq!(contract.get_bid_increment_bounds(), (U128(100), U128(5000)));
+
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment": "9" })), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot have both an absolute and a percentage minimum bid increment")]
+    fn increments_are_either_absolute_or_a_percentage() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment": "100", "min_bid_increment_bps": 100 })), NOW);
+    }
+
+    //an auction starting at 100 with bid increments of 10
+    fn proxy_auction() -> Contract {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(100, json!({ "min_bid_increment": "10" })), NOW);
+        contract
+    }
+
+    fn proxy_bid(contract: &mut Contract, bidder_id: AccountId, max_bid: u128, timestamp: u64) {
+        deposit_storage(contract, bidder_id.clone(), timestamp);
+        call(bidder_id, max_bid, timestamp);
+        contract.add_proxy_bid(nft(), "1".to_string());
+    }
+
+    fn current_bid(contract: &Contract) -> (AccountId, u128) {
+        let bid = sale(contract, "1").unwrap().bids.unwrap().pop().unwrap();
+        (bid.bidder_id, bid.price.0)
+    }
+
+    #[test]
+    fn proxy_bids_only_beat_the_current_bid_by_one_increment() {
+        let mut contract = proxy_auction();
+        bid(&mut contract, bob(), 200, NOW + 1);
+        proxy_bid(&mut contract, carol(), 500, NOW + 2);
+
+        assert_eq!(current_bid(&contract), (carol(), 210));
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 200)]);
+    }
+
+    #[test]
+    fn bids_below_the_proxy_maximum_are_refunded_and_outbid() {
+        let mut contract = proxy_auction();
+        proxy_bid(&mut contract, carol(), 500, NOW + 1);
+        assert_eq!(current_bid(&contract), (carol(), 110));
+
+        bid(&mut contract, bob(), 300, NOW + 2);
+        assert_eq!(current_bid(&contract), (carol(), 310));
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 300)]);
+    }
+
+    #[test]
+    fn raising_your_own_maximum_keeps_your_bid() {
+        let mut contract = proxy_auction();
+        proxy_bid(&mut contract, carol(), 500, NOW + 1);
+        proxy_bid(&mut contract, carol(), 800, NOW + 2);
+
+        assert_eq!(current_bid(&contract), (carol(), 110));
+        //the market only holds the new maximum
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 500)]);
+    }
+
+    #[test]
+    fn proxy_winners_only_pay_their_winning_bid() {
+        let mut contract = proxy_auction();
+        bid(&mut contract, bob(), 200, NOW + 1);
+        proxy_bid(&mut contract, carol(), 500, NOW + 2);
+
+        call(alice(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 290)]);
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("210"));
+    }
+
+    #[test]
+    fn second_price_auctions_refund_the_winner_down_to_the_runner_up_plus_an_increment() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(100, json!({ "min_bid_increment": "10", "is_second_price": true })), NOW);
+        bid(&mut contract, bob(), 200, NOW + 1);
+        bid(&mut contract, carol(), 400, NOW + 2);
+
+        call(alice(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 190)]);
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("210"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only auctions can be second price auctions")]
+    fn fixed_price_sales_cannot_be_second_price() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "100" }, "is_auction": false, "is_second_price": true }), NOW);
+    }
+
+    //the NEP-297 events the last call logged, as (event, data)
+    fn events() -> Vec<(String, Value)> {
+        near_sdk::test_utils::get_logs()
+            .into_iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:").map(|event| near_sdk::serde_json::from_str::<Value>(event).unwrap()))
+            .map(|event| (event["event"].as_str().unwrap().to_string(), event["data"][0].clone()))
+            .collect()
+    }
+
+    //an auction starting at 1000 that is relisted once at 800 for a second and then becomes a fixed price sale at 600
+    fn relisted_auction() -> Contract {
+        let mut contract = new_market();
+        let args = json!({
+            "relist_count": 1,
+            "relist_duration": "1000000000",
+            "relist_starting_price": "800",
+            "relist_fixed_price": "600",
+        });
+        list(&mut contract, alice(), "1", auction_msg(1000, args), NOW);
+        contract
+    }
+
+    #[test]
+    fn auctions_without_bids_are_relisted_and_then_turned_into_fixed_price_sales() {
+        let mut contract = relisted_auction();
+
+        call(bob(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+        let relisted = sale(&contract, "1").unwrap();
+        assert_eq!((relisted.start_time, relisted.end_time, relisted.relists_left), (Some(NOW + 1001), Some(NOW + 1_000_001_001), 0));
+        assert_eq!(relisted.auction_condition(), (near_token_id(), 800));
+        assert_eq!(events()[0].0, "auction_relisted");
+        assert_eq!(events()[0].1["starting_price"], "800");
+
+        call(bob(), 0, NOW + 1_000_001_002);
+        contract.end_auction(nft(), "1".to_string());
+        let converted = sale(&contract, "1").unwrap();
+        assert!(!converted.is_auction);
+        assert_eq!(converted.sale_conditions.get(&near_token_id()), Some(&U128(600)));
+        assert_eq!(events(), vec![(
+            "auction_converted_to_fixed_price".to_string(),
+            json!({ "nft_contract_id": nft(), "token_id": "1", "ft_token_id": "near", "price": "600" }),
+        )]);
+
+        //the converted sale is bought like any other fixed price sale
+        call(bob(), 600, NOW + 1_000_001_003);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("600"));
+    }
+
+    #[test]
+    fn converted_sales_keep_none_of_the_auction_settings() {
+        let mut contract = new_market();
+        let args = json!({
+            "reserve_price": "2000",
+            "hide_reserve_price": true,
+            "buy_now_price": "5000",
+            "extension_window": "1000000000",
+            "extension_duration": "1000000000",
+            "min_bid_increment": "100",
+            "is_second_price": true,
+            "relist_fixed_price": "600",
+        });
+        list(&mut contract, alice(), "1", auction_msg(1000, args), NOW);
+
+        call(bob(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+        let converted = sale(&contract, "1").unwrap();
+        assert!(!converted.is_auction && !converted.is_second_price && !converted.hide_reserve_price);
+        assert_eq!((converted.reserve_price, converted.buy_now_price, converted.min_bid_increment), (None, None, None));
+        assert_eq!((converted.extension_window, converted.extension_duration, converted.end_time), (None, None, None));
+        assert!(contract.get_reserve_info(format!("{}{}1", nft(), DELIMETER)).is_none());
+    }
+
+    #[test]
+    fn auctions_without_relists_are_taken_down() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({})), NOW);
+
+        call(bob(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+        assert!(sale(&contract, "1").is_none());
+        assert!(events().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Relist starting price must be greater than 0 and at most the starting price")]
+    fn relists_cannot_start_above_the_starting_price() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "relist_count": 1, "relist_starting_price": "1001" })), NOW);
+    }
+
+    #[test]
+    fn durations_start_when_the_approval_lands() {
+        let mut contract = new_market();
+        let msg = json!({
+            "sale_conditions": { "near": "1000" },
+            "is_auction": true,
+            "duration": "3600000000000",
+            "start_delay": "60000000000",
+        });
+        list(&mut contract, alice(), "1", msg, NOW);
+
+        let auction = sale(&contract, "1").unwrap();
+        assert_eq!(auction.start_time, Some(NOW + 60_000_000_000));
+        assert_eq!(auction.end_time, Some(NOW + 3_660_000_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "end_time must be a Unix timestamp in nanoseconds, got 1700000000")]
+    fn timestamps_in_seconds_are_rejected() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "end_time": "1700000000" })), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "duration must be in nanoseconds and at least 1000000000 (1 second), got 3600")]
+    fn durations_in_seconds_are_rejected() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "1000" }, "is_auction": true, "duration": "3600" }), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "extension_window must be in nanoseconds and at least 1000000000 (1 second), got 100")]
+    fn extension_windows_in_seconds_are_rejected() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "extension_window": "100", "extension_duration": "300" })), NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "start_delay must be in nanoseconds and at least 1000000000 (1 second), got 60")]
+    fn start_delays_in_seconds_are_rejected() {
+        let mut contract = new_market();
+        let msg = json!({ "sale_conditions": { "near": "1000" }, "is_auction": true, "duration": "3600000000000", "start_delay": "60" });
+        list(&mut contract, alice(), "1", msg, NOW);
+    }
+
+    #[test]
+    #[should_panic(expected = "Use either start_time and end_time or duration, not both")]
+    fn durations_cannot_be_mixed_with_timestamps() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "duration": "3600000000000" })), NOW);
+    }
 }
@@ -1,29 +1,57 @@
+#![allow(clippy::too_many_arguments)]
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Balance, Gas, PanicOnDefault,
-    Promise, CryptoHash, BorshStorageKey,
+    Promise, PromiseOrValue, CryptoHash, BorshStorageKey,
 };
 use std::collections::HashMap;
 
 use crate::external::*;
 //use crate::internal::*;
 use crate::sale::*;
+use crate::sealed_bids::*;
+use crate::collection_offers::*;
+use crate::offer_books::OfferBook;
+use crate::token_offers::*;
+use crate::buy_orders::*;
+use crate::negotiations::*;
+use crate::fee_table::*;
 
 use near_sdk::env::STORAGE_PRICE_PER_BYTE;
 
 mod external;
 mod internal;
-mod nft_callbacks;
+pub mod ft_callbacks;
+pub mod nft_callbacks;
 mod sale;
 mod sale_views;
 mod contract_ids_for_account;
+mod ft_token_ids;
+mod sealed_bids;
+mod bid_history;
+mod pending_balances;
+mod settlement;
+mod events;
+mod collection_offers;
+mod offer_books;
+mod token_offers;
+mod buy_orders;
+mod negotiations;
+mod collection_fees;
+mod fee_table;
+mod migrate;
+mod fee_ledger;
+#[cfg(test)]
+mod test_utils;
 
 //GAS constants to attach to calls
-const GAS_FOR_ROYALTIES: Gas = Gas(115_000_000_000_000);
+const GAS_FOR_ROYALTIES: Gas = Gas(150_000_000_000_000);
 const GAS_FOR_NFT_TRANSFER: Gas = Gas(15_000_000_000_000);
+const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_RESOLVE_PAYMENT: Gas = Gas(5_000_000_000_000);
 
 //constant used to attach 0 NEAR to a call
 const NO_DEPOSIT: Balance = 0;
@@ -34,18 +62,24 @@ const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
 //every sale will have a unique ID which is `CONTRACT + DELIMITER + TOKEN_ID`
 static DELIMETER: &str = ".";
 
+//the fungible token ID used for sales that are priced in yoctoNEAR
+static NEAR_TOKEN_ID: &str = "near";
+
 //Creating custom types to use within the contract. This makes things more readable. 
 pub type SalePriceInYoctoNear = U128;
 pub type TokenId = String;
 pub type FungibleTokenId = AccountId;
 pub type ContractAndTokenId = String;
+//the price of a sale for each fungible token it can be bought with ("near" for yoctoNEAR)
+pub type SaleConditions = HashMap<FungibleTokenId, U128>;
 
 //Bid object
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Bid {
     pub bidder_id: AccountId,
     pub price: U128,
+    pub timestamp: U64, //block timestamp the bid was placed at
 }
 
 //bid array
@@ -66,11 +100,15 @@ pub struct Contract {
     //keep track of the owner of the contract
     pub owner_id: AccountId,
 
-    pub charges_id: AccountId,
-    pub commissions_id: AccountId,
+    //accounts paid a part (in basis points) of the price of every sale, for collections without their own fee table
+    pub fee_table: Vec<FeeEntry>,
+
+    //part of the price (in basis points) paid out of the fees to keepers that settle expired auctions
+    pub keeper_fee: u128,
 
-    pub charges: u128,
-    pub commissions: u128,
+    //bounds (in basis points) for the minimum bid increments sellers can set on auctions
+    pub min_bid_increment_bps: u128,
+    pub max_bid_increment_bps: u128,
     
     /*
         to keep track of the sales, we map the ContractAndTokenId to a Sale. 
@@ -90,6 +128,71 @@ pub struct Contract {
 
     //keep track of contracts that the account wants to see tokens of
     pub contract_ids_by_account_id: LookupMap<AccountId, UnorderedSet<AccountId>>,
+
+    //keep track of the fungible tokens (including NEAR) that sales can be priced in
+    pub ft_token_ids: UnorderedSet<FungibleTokenId>,
+
+    //keep track of the sealed bids committed on each sealed bid auction
+    pub sealed_bids: LookupMap<ContractAndTokenId, UnorderedMap<AccountId, SealedBid>>,
+
+    //keep track of every bid placed on each auction and of the accounts that placed them, by sale ID and auction
+    //sequence number. The history is kept after the auction is over
+    pub bid_history: LookupMap<(ContractAndTokenId, u64), Vector<Bid>>,
+    pub bidders: LookupMap<(ContractAndTokenId, u64), UnorderedSet<AccountId>>,
+
+    //keep track of how many auctions have been held for each sale ID
+    pub auction_counts: LookupMap<ContractAndTokenId, u64>,
+
+    //keep track of refunds and payouts that failed and of returned sealed bid deposits, so the accounts can withdraw them later
+    pub pending_balances: LookupMap<AccountId, UnorderedMap<FungibleTokenId, Balance>>,
+
+    //keep track of the auctions by the time they can be settled (end time, or reveal end time for sealed bid auctions)
+    pub auctions_by_end_time: TreeMap<(u64, ContractAndTokenId), ()>,
+
+    //ID given to the next offer or buy order
+    pub next_offer_id: u64,
+
+    //keep track of the collection offers by their offer ID
+    pub collection_offers: LookupMap<u64, CollectionOffer>,
+
+    //keep track of the collection offer IDs for each collection, ordered by price
+    pub collection_offer_book: LookupMap<AccountId, OfferBook>,
+
+    //keep track of the collection offer IDs for each buyer
+    pub collection_offers_by_buyer_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    //keep track of the token offers by their offer ID
+    pub token_offers: LookupMap<u64, TokenOffer>,
+
+    //keep track of the token offer IDs for each token, ordered by price
+    pub token_offer_book: LookupMap<ContractAndTokenId, OfferBook>,
+
+    //keep track of the token offer IDs for each buyer
+    pub token_offers_by_buyer_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    //keep track of the buy orders by their order ID
+    pub buy_orders: LookupMap<u64, BuyOrder>,
+
+    //keep track of the buy order IDs for each collection, ordered by price
+    pub buy_order_book: LookupMap<AccountId, OfferBook>,
+
+    //keep track of the buy order IDs for each buyer
+    pub buy_orders_by_buyer_id: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    //keep track of the seller's current counter offer for each token offer
+    pub counter_offers: LookupMap<u64, CounterOffer>,
+
+    //keep track of every round of the negotiation over each token offer
+    pub negotiation_rounds: LookupMap<u64, Vector<NegotiationRound>>,
+
+    //keep track of the fee tables negotiated with each collection
+    pub collection_fees: UnorderedMap<AccountId, Vec<FeeEntry>>,
+
+    //keep track of the fees each fee recipient can withdraw, for every fungible token
+    pub fee_balances: LookupMap<AccountId, UnorderedMap<FungibleTokenId, Balance>>,
+
+    //keep track of the fees accrued on each collection's sales over time, for every fungible token
+    pub fees_accrued: UnorderedMap<AccountId, UnorderedMap<FungibleTokenId, Balance>>,
 }
 
 /// Helper structure to for keys of the persistent collections.
@@ -106,6 +209,39 @@ pub enum StorageKey {
     StorageDeposits,
     AccountContractIds,
     ContractIdsInner { account_id_hash: CryptoHash},
+    SealedBids,
+    SealedBidsInner { sale_id_hash: CryptoHash },
+    BidHistory,
+    BidHistoryInner { auction_id_hash: CryptoHash },
+    Bidders,
+    BiddersInner { auction_id_hash: CryptoHash },
+    PendingBalances,
+    PendingBalancesInner { account_id_hash: CryptoHash },
+    AuctionsByEndTime,
+    CollectionOffers,
+    CollectionOfferBook,
+    CollectionOfferBookInner { account_id_hash: CryptoHash },
+    CollectionOffersByBuyerId,
+    CollectionOffersByBuyerIdInner { account_id_hash: CryptoHash },
+    TokenOffers,
+    TokenOfferBook,
+    TokenOfferBookInner { sale_id_hash: CryptoHash },
+    TokenOffersByBuyerId,
+    TokenOffersByBuyerIdInner { account_id_hash: CryptoHash },
+    BuyOrders,
+    BuyOrderBook,
+    BuyOrderBookInner { account_id_hash: CryptoHash },
+    BuyOrdersByBuyerId,
+    BuyOrdersByBuyerIdInner { account_id_hash: CryptoHash },
+    CounterOffers,
+    NegotiationRounds,
+    NegotiationRoundsInner { offer_id: u64 },
+    CollectionFees,
+    FeeBalances,
+    FeeBalancesInner { account_id_hash: CryptoHash },
+    FeesAccrued,
+    FeesAccruedInner { account_id_hash: CryptoHash },
+    AuctionCounts,
 }
 
 #[near_bindgen]
@@ -118,27 +254,51 @@ impl Contract {
     #[init]
     pub fn new(owner_id: AccountId, charges_id: AccountId, charges: u128, commissions_id: AccountId, commissions: u128) -> Self {
 
-        assert!(
-            charges + commissions <= 6000,
-            "Cannot have the sum of charges and commissions to be greater than 60%"
-        );
+        //the market starts with a fee table of its charges and commissions
+        let fee_table = charges_and_commissions_fee_table(charges_id, charges, commissions_id, commissions);
+        assert_valid_fee_table(&fee_table);
 
-        let this = Self {
+        let mut this = Self {
             //set the owner_id field equal to the passed in owner_id. 
             owner_id,
-            charges_id,
-            commissions_id,
-
-            charges,
-            commissions,
+            fee_table,
+            keeper_fee: 0,
+            //sellers can pick any increment up to 50% of the bid until the owner changes the bounds
+            min_bid_increment_bps: 0,
+            max_bid_increment_bps: 5000,
             //Storage keys are simply the prefixes used for the collections. This helps avoid data collision
             sales: UnorderedMap::new(StorageKey::Sales),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
             by_nft_contract_id: UnorderedMap::new(StorageKey::ByNFTContractId),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
             contract_ids_by_account_id: LookupMap::new(StorageKey::AccountContractIds),
+            ft_token_ids: UnorderedSet::new(StorageKey::FTTokenIds),
+            sealed_bids: LookupMap::new(StorageKey::SealedBids),
+            bid_history: LookupMap::new(StorageKey::BidHistory),
+            bidders: LookupMap::new(StorageKey::Bidders),
+            auction_counts: LookupMap::new(StorageKey::AuctionCounts),
+            pending_balances: LookupMap::new(StorageKey::PendingBalances),
+            auctions_by_end_time: TreeMap::new(StorageKey::AuctionsByEndTime),
+            next_offer_id: 0,
+            collection_offers: LookupMap::new(StorageKey::CollectionOffers),
+            collection_offer_book: LookupMap::new(StorageKey::CollectionOfferBook),
+            collection_offers_by_buyer_id: LookupMap::new(StorageKey::CollectionOffersByBuyerId),
+            token_offers: LookupMap::new(StorageKey::TokenOffers),
+            token_offer_book: LookupMap::new(StorageKey::TokenOfferBook),
+            token_offers_by_buyer_id: LookupMap::new(StorageKey::TokenOffersByBuyerId),
+            buy_orders: LookupMap::new(StorageKey::BuyOrders),
+            buy_order_book: LookupMap::new(StorageKey::BuyOrderBook),
+            buy_orders_by_buyer_id: LookupMap::new(StorageKey::BuyOrdersByBuyerId),
+            counter_offers: LookupMap::new(StorageKey::CounterOffers),
+            negotiation_rounds: LookupMap::new(StorageKey::NegotiationRounds),
+            collection_fees: UnorderedMap::new(StorageKey::CollectionFees),
+            fee_balances: LookupMap::new(StorageKey::FeeBalances),
+            fees_accrued: UnorderedMap::new(StorageKey::FeesAccrued),
         };
 
+        //NEAR is always accepted
+        this.ft_token_ids.insert(&internal::near_token_id());
+
         //return the Contract object
         this
     }
@@ -149,8 +309,6 @@ impl Contract {
     pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
         //get the account ID to pay for storage for
         let storage_account_id = account_id 
-            //convert the valid account ID into an account ID
-            .map(|a| a.into())
             //if we didn't specify an account ID, we simply use the caller of the function
             .unwrap_or_else(env::predecessor_account_id);
 
@@ -188,9 +346,9 @@ impl Contract {
         
         //how many sales is that user taking up currently. This returns a set
         let sales = self.by_owner_id.get(&owner_id);
-        //get the length of that set. 
-        let len = sales.map(|s| s.len()).unwrap_or_default();
-        //how much NEAR is being used up for all the current sales on the account 
+        //get the length of that set, plus the offers the user has open
+        let len = sales.map(|s| s.len()).unwrap_or_default() + self.internal_offer_count(&owner_id);
+        //how much NEAR is being used up for all the current sales and offers on the account 
         let diff = u128::from(len) * STORAGE_PER_SALE;
 
         //the excess to withdraw is the total storage paid - storage being used up.
@@ -219,27 +377,8 @@ impl Contract {
         U128(self.storage_deposits.get(&account_id).unwrap_or(0))
     }
 
-    // if the owner ever wants to move their charges somewhere
-    pub fn change_charges_id(&mut self, charges_id: AccountId) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "only owner"
-        );
-        self.charges_id = charges_id;
-    }
-
-    // if the owner wants to move their commissions
-    pub fn change_commissions_id(&mut self, commissions_id: AccountId) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "only owner"
-        );
-        self.commissions_id = commissions_id;
-    }
-
-    pub fn change_charges(&mut self, charges: u128) {
+    //sets the bounds (in basis points) for the minimum bid increments sellers can set on auctions
+    pub fn change_bid_increment_bounds(&mut self, min_bid_increment_bps: u128, max_bid_increment_bps: u128) {
         assert_eq!(
             env::predecessor_account_id(),
             self.owner_id,
@@ -247,19 +386,21 @@ impl Contract {
         );
 
         assert!(
-            charges <= 10_000,
-            "Cannot increase charges more than 10,000 (100%)"
+            min_bid_increment_bps <= max_bid_increment_bps,
+            "Minimum bound cannot be greater than the maximum bound"
         );
 
         assert!(
-            charges + self.commissions <= 6000,
-            "Cannot have the sum of charges and commissions to be greater than 60%"
+            max_bid_increment_bps <= 10_000,
+            "Cannot increase the maximum bid increment more than 10,000 (100%)"
         );
 
-        self.charges = charges;
+        self.min_bid_increment_bps = min_bid_increment_bps;
+        self.max_bid_increment_bps = max_bid_increment_bps;
     }
 
-    pub fn change_commissions(&mut self, commissions: u128) {
+    //sets the part of the price (in basis points) keepers are paid for settling an auction. It is taken out of the fees
+    pub fn change_keeper_fee(&mut self, keeper_fee: u128) {
         assert_eq!(
             env::predecessor_account_id(),
             self.owner_id,
@@ -267,15 +408,10 @@ impl Contract {
         );
 
         assert!(
-            commissions <= 10_000,
-            "Cannot increase commissions more than 10,000 (100%)"
-        );
-        
-        assert!(
-            self.charges + commissions <= 6000,
-            "Cannot have the sum of charges and commissions to be greater than 60%"
+            keeper_fee <= 10_000,
+            "Cannot increase keeper fee more than 10,000 (100%)"
         );
 
-        self.commissions = commissions;
+        self.keeper_fee = keeper_fee;
     }
 }
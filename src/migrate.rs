@@ -0,0 +1,226 @@
+use crate::*;
+use crate::internal::near_token_id;
+
+//a bid as it was stored before bids had timestamps
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldBid {
+    pub bidder_id: AccountId,
+    pub price: U128,
+}
+
+//a sale as it was stored before sales could be priced in fungible tokens, when every price was in yoctoNEAR
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldSale {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: String,
+    pub token_id: String,
+    pub price: u128,
+    pub bids: Option<Vec<OldBid>>,
+    pub is_auction: bool,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+}
+
+//the contract state of deployed markets, which pay exactly two fees: charges and commissions
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldContract {
+    pub owner_id: AccountId,
+    pub charges_id: AccountId,
+    pub commissions_id: AccountId,
+    pub charges: u128,
+    pub commissions: u128,
+    //the values are OldSales until migrate_sales stores them in the current layout
+    pub sales: UnorderedMap<ContractAndTokenId, Sale>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_nft_contract_id: UnorderedMap<AccountId, UnorderedSet<TokenId>>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    pub contract_ids_by_account_id: LookupMap<AccountId, UnorderedSet<AccountId>>,
+}
+
+impl OldSale {
+    //the sale in the current layout. The price becomes the sale's NEAR price and everything added since gets its default
+    fn into_sale(self) -> Sale {
+        let mut sale = Sale::new_fixed_price(
+            self.owner_id,
+            self.approval_id,
+            self.nft_contract_id.parse().unwrap(),
+            self.token_id,
+            near_token_id(),
+            self.price,
+        );
+        sale.bids = self.bids.map(|bids| {
+            bids.into_iter()
+                .map(|bid| Bid {
+                    bidder_id: bid.bidder_id,
+                    price: bid.price,
+                    timestamp: U64(0), //bids placed before timestamps were stored
+                })
+                .collect()
+        });
+        sale.is_auction = self.is_auction;
+        sale.start_time = self.start_time;
+        sale.end_time = self.end_time;
+        sale
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /*
+        migrates a deployed market to the current layout. The charges and commissions become the fee table and everything
+        the market has gained since starts out empty, like in a new market. The sales are left in the old layout for
+        migrate_sales. This is called once, right after deploying the new code
+    */
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old_state: OldContract = env::state_read().expect("No state to migrate");
+
+        let mut this = Self::new(
+            old_state.owner_id,
+            old_state.charges_id,
+            old_state.charges,
+            old_state.commissions_id,
+            old_state.commissions,
+        );
+        this.by_owner_id = old_state.by_owner_id;
+        this.by_nft_contract_id = old_state.by_nft_contract_id;
+        this.storage_deposits = old_state.storage_deposits;
+        this.contract_ids_by_account_id = old_state.contract_ids_by_account_id;
+        this.sales = old_state.sales;
+
+        this
+    }
+
+    /*
+        stores up to limit sales of a migrated market in the current layout, starting with the sale at from_index, and
+        returns the index to continue from. It is called with 0 and then every returned index until that is the number
+        of sales, right after migrate and before the market is used, since sales in the old layout can't be read
+    */
+    #[private]
+    pub fn migrate_sales(&mut self, from_index: U64, limit: u64) -> U64 {
+        let end_index = std::cmp::min(from_index.0 + limit, self.sales.len());
+
+        for index in from_index.0..end_index {
+            let contract_and_token_id = self.sales.keys_as_vector().get(index).unwrap();
+            let old_sale = self.sales.values_as_vector().get_raw(index).unwrap();
+            let mut sale = OldSale::try_from_slice(&old_sale).expect("Sale is already migrated").into_sale();
+
+            //the sale is replaced in place, so its index doesn't change. Auctions are added to the end time index
+            //so they can be settled like any other auction
+            if sale.is_auction {
+                sale.auction_seq = self.internal_next_auction_seq(&contract_and_token_id);
+            }
+            self.sales.insert_raw(&contract_and_token_id.try_to_vec().unwrap(), &sale.try_to_vec().unwrap());
+            self.internal_index_auction(&contract_and_token_id, &sale);
+        }
+
+        U64(end_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn old_sale(token_id: &str, price: u128, bids: Option<Vec<OldBid>>) -> OldSale {
+        OldSale {
+            owner_id: alice(),
+            approval_id: 1,
+            nft_contract_id: nft().to_string(),
+            token_id: token_id.to_string(),
+            price,
+            is_auction: bids.is_some(),
+            bids,
+            start_time: Some(NOW),
+            end_time: Some(NOW + 1_000),
+        }
+    }
+
+    //writes the state of a deployed market with the given charges and commissions, a sale of token 1 and an auction of
+    //token 2 with a bid
+    fn deployed_market_with_fees(charges_id: &str, charges: u128, commissions_id: &str, commissions: u128) {
+        call(market(), 0, NOW);
+        let mut old_state = OldContract {
+            owner_id: account("owner.near"),
+            charges_id: account(charges_id),
+            commissions_id: account(commissions_id),
+            charges,
+            commissions,
+            sales: UnorderedMap::new(StorageKey::Sales),
+            by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
+            by_nft_contract_id: UnorderedMap::new(StorageKey::ByNFTContractId),
+            storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            contract_ids_by_account_id: LookupMap::new(StorageKey::AccountContractIds),
+        };
+        let old_sales = [
+            ("1", old_sale("1", 100, None)),
+            ("2", old_sale("2", 50, Some(vec![OldBid { bidder_id: bob(), price: U128(70) }]))),
+        ];
+        for (token_id, old_sale) in old_sales {
+            let contract_and_token_id = format!("{}{}{}", nft(), DELIMETER, token_id);
+            old_state.sales.insert_raw(&contract_and_token_id.try_to_vec().unwrap(), &old_sale.try_to_vec().unwrap());
+        }
+        old_state.storage_deposits.insert(&alice(), &(2 * STORAGE_PER_SALE));
+        env::state_write(&old_state);
+    }
+
+    //a deployed market that charges 8%
+    fn deployed_market() {
+        deployed_market_with_fees("charges.near", 800, "commissions.near", 0);
+    }
+
+    #[test]
+    fn migrate_turns_charges_and_commissions_into_the_fee_table() {
+        deployed_market();
+
+        let contract = Contract::migrate();
+        //commissions of 0 are left out of the fee table
+        let fee_table: Vec<(AccountId, u128)> = contract.get_fee_table().into_iter().map(|entry| (entry.account_id, entry.bps)).collect();
+        assert_eq!(fee_table, vec![(account("charges.near"), 800)]);
+        assert!(contract.ft_token_ids.contains(&near_token_id()));
+        assert_eq!(contract.storage_balance_of(alice()), U128(2 * STORAGE_PER_SALE));
+    }
+
+    #[test]
+    fn migrate_adds_up_charges_and_commissions_to_the_same_account() {
+        deployed_market_with_fees("fees.near", 800, "fees.near", 100);
+
+        let contract = Contract::migrate();
+        let fee_table: Vec<(AccountId, u128)> = contract.get_fee_table().into_iter().map(|entry| (entry.account_id, entry.bps)).collect();
+        assert_eq!(fee_table, vec![(account("fees.near"), 900)]);
+    }
+
+    #[test]
+    fn migrate_keeps_the_sales_priced_in_near() {
+        deployed_market();
+
+        //the sales are moved to the current layout a page at a time
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.migrate_sales(U64(0), 1), U64(1));
+        assert_eq!(contract.migrate_sales(U64(1), 10), U64(2));
+        assert_eq!(contract.sales.len(), 2);
+        let fixed_price = sale(&contract, "1").unwrap();
+        assert_eq!(fixed_price.sale_conditions.get(&near_token_id()), Some(&U128(100)));
+        assert!(!fixed_price.is_auction);
+
+        //auctions keep their bids and can be settled once they're over
+        let auction = sale(&contract, "2").unwrap();
+        assert!(auction.is_auction);
+        assert_eq!(auction.current_bid_escrow().unwrap().1, 70);
+        call(carol(), 0, NOW + 1001);
+        assert_eq!(contract.get_expired_auctions(Some(10)), vec![format!("{}{}2", nft(), DELIMETER)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale is already migrated")]
+    fn sales_cannot_be_migrated_twice() {
+        deployed_market();
+
+        let mut contract = Contract::migrate();
+        contract.migrate_sales(U64(0), 10);
+        contract.migrate_sales(U64(0), 10);
+    }
+}
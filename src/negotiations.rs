@@ -0,0 +1,309 @@
+use crate::*;
+use crate::internal::{near_token_id, pay_account};
+use crate::offer_books::{assert_valid_expiry, is_expired};
+
+//the most rounds a negotiation can have, counting the buyer's offer
+const MAX_NEGOTIATION_ROUNDS: u64 = 20;
+
+//struct for the seller's answer to a token offer. The approval is the one the seller gave the market with the counter offer
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CounterOffer {
+    pub seller_id: AccountId,
+    pub approval_id: u64,
+    pub price: U128, //in yoctoNEAR
+    pub expires_at: Option<U64>, //Unix timestamp (in nanoseconds) the buyer can't accept the counter offer after
+}
+
+impl CounterOffer {
+    //whether the counter offer is past its expiry
+    pub(crate) fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+}
+
+//struct for one round of the negotiation over a token offer
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NegotiationRound {
+    pub account_id: AccountId, //the buyer for their offer and the seller for a counter offer
+    pub price: U128,
+    pub expires_at: Option<U64>,
+    pub timestamp: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    //accepts the seller's counter offer on a token offer. The attached deposit tops up the escrowed offer to the
+    //counter price and anything above that is refunded. The token is then sold like a fixed price sale
+    #[payable]
+    pub fn accept_counter_offer(&mut self, offer_id: u64) -> Promise {
+        let offer = self.token_offers.get(&offer_id).expect("No token offer");
+        assert_eq!(env::predecessor_account_id(), offer.buyer_id, "Only the buyer can accept a counter offer");
+        assert!(!offer.is_expired(), "Offer {} has expired", offer_id);
+
+        let counter_offer = self.counter_offers.get(&offer_id).expect("No counter offer");
+        assert!(!counter_offer.is_expired(), "Counter offer has expired");
+
+        let escrow = offer.price.0 + env::attached_deposit();
+        assert!(
+            escrow >= counter_offer.price.0,
+            "Offer and deposit must be greater than or equal to the counter price: {:?}",
+            counter_offer.price
+        );
+
+        //a token that is being auctioned can't be sold through a counter offer. A fixed price listing is taken off the market
+        let contract_and_token_id = format!("{}{}{}", offer.nft_contract_id, DELIMETER, offer.token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            assert!(!sale.is_auction, "Token is being auctioned");
+            self.internal_remove_sale(offer.nft_contract_id.clone(), offer.token_id.clone());
+        }
+
+        //removing the offer also removes the counter offer
+        let offer = self.internal_remove_token_offer(offer_id);
+        if escrow > counter_offer.price.0 {
+            pay_account(&near_token_id(), offer.buyer_id.clone(), escrow - counter_offer.price.0);
+        }
+
+        let sale = Sale::new_fixed_price(
+            counter_offer.seller_id,
+            counter_offer.approval_id,
+            offer.nft_contract_id,
+            offer.token_id,
+            near_token_id(),
+            counter_offer.price.0,
+        );
+        self.internal_transfer_and_pay(sale, near_token_id(), counter_offer.price, offer.buyer_id, None)
+    }
+
+    /// views
+    //returns the seller's current counter offer on a token offer
+    pub fn get_counter_offer(&self, offer_id: u64) -> Option<CounterOffer> {
+        self.counter_offers.get(&offer_id)
+    }
+
+    //returns every round of the negotiation over a token offer, oldest first. The rounds are kept after the offer is
+    //accepted, cancelled or removed
+    pub fn get_negotiation(&self, offer_id: u64) -> Vec<NegotiationRound> {
+        self.negotiation_rounds
+            .get(&offer_id)
+            .map(|rounds| rounds.to_vec())
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    //internal method for answering a token offer with a counter price when the token's owner calls nft_approve with a
+    //counter_offer msg. A new counter offer replaces the previous one and every round is kept, paid for out of the
+    //owner's storage deposit
+    pub(crate) fn internal_counter_token_offer(
+        &mut self,
+        owner_id: AccountId,
+        approval_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        offer_id: u64,
+        price: U128,
+        expires_at: Option<U64>,
+    ) {
+        let offer = self.token_offers.get(&offer_id).expect("No token offer");
+        assert!(
+            offer.nft_contract_id == nft_contract_id && offer.token_id == token_id,
+            "Offer {} is not for this token",
+            offer_id
+        );
+        assert!(!offer.is_expired(), "Offer {} has expired", offer_id);
+        assert_ne!(offer.buyer_id, owner_id, "Cannot counter your own offer");
+        assert!(price.0 > offer.price.0, "Counter price must be greater than the offer. Accept the offer instead");
+
+        assert_valid_expiry(expires_at);
+
+        //approving the market again replaces its old approval, so a listing of the token has to use the new one
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(mut sale) = self.sales.get(&contract_and_token_id) {
+            assert!(!sale.is_auction, "Token is being auctioned");
+            sale.approval_id = approval_id;
+            self.sales.insert(&contract_and_token_id, &sale);
+        }
+
+        //get the rounds for the offer. If there are none, we create a new vector that starts with the buyer's offer
+        let initial_storage_usage = env::storage_usage();
+        let mut rounds = self.negotiation_rounds.get(&offer_id).unwrap_or_else(|| {
+            let mut rounds = Vector::new(
+                StorageKey::NegotiationRoundsInner { offer_id }
+                    .try_to_vec()
+                    .unwrap(),
+            );
+            rounds.push(&NegotiationRound {
+                account_id: offer.buyer_id.clone(),
+                price: offer.price,
+                expires_at: offer.expires_at,
+                timestamp: U64(env::block_timestamp()),
+            });
+            rounds
+        });
+        assert!(rounds.len() < MAX_NEGOTIATION_ROUNDS, "A negotiation can have at most {} rounds", MAX_NEGOTIATION_ROUNDS);
+        rounds.push(&NegotiationRound {
+            account_id: owner_id.clone(),
+            price,
+            expires_at,
+            timestamp: U64(env::block_timestamp()),
+        });
+        self.negotiation_rounds.insert(&offer_id, &rounds);
+        self.internal_charge_storage(&owner_id, initial_storage_usage);
+
+        self.counter_offers.insert(
+            &offer_id,
+            &CounterOffer {
+                seller_id: owner_id,
+                approval_id,
+                price,
+                expires_at,
+            },
+        );
+    }
+
+    //internal method for removing the counter offer of a token offer that was removed. The negotiation rounds are
+    //kept as the history of the offer
+    pub(crate) fn internal_remove_counter_offer(&mut self, offer_id: u64) {
+        self.counter_offers.remove(&offer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    //a 500 offer by bob on alice's token 1. Alice pays for the storage of the negotiation unless told not to
+    fn offer_without_seller_storage() -> (Contract, u64) {
+        let mut contract = new_market();
+        deposit_storage(&mut contract, bob(), NOW);
+        call(bob(), 500, NOW);
+        let offer_id = contract.make_token_offer(nft(), "1".to_string(), None);
+        (contract, offer_id)
+    }
+
+    fn offer() -> (Contract, u64) {
+        let (mut contract, offer_id) = offer_without_seller_storage();
+        deposit_storage(&mut contract, alice(), NOW);
+        (contract, offer_id)
+    }
+
+    fn counter(contract: &mut Contract, offer_id: u64, price: u128, expires_at: Option<u64>) {
+        let mut counter_offer = json!({ "offer_id": offer_id, "price": price.to_string() });
+        if let Some(expires_at) = expires_at {
+            counter_offer["expires_at"] = json!(expires_at.to_string());
+        }
+        approve(contract, alice(), "1", json!({ "counter_offer": counter_offer }), NOW + 1);
+    }
+
+    #[test]
+    fn every_round_of_the_negotiation_is_kept() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 900, None);
+        counter(&mut contract, offer_id, 800, None);
+
+        let rounds: Vec<(AccountId, u128)> = contract.get_negotiation(offer_id).into_iter().map(|round| (round.account_id, round.price.0)).collect();
+        assert_eq!(rounds, vec![(bob(), 500), (alice(), 900), (alice(), 800)]);
+        assert_eq!(contract.get_counter_offer(offer_id).unwrap().price, U128(800));
+    }
+
+    #[test]
+    fn the_negotiation_is_kept_after_the_offer_is_gone() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 900, None);
+
+        call(bob(), 1, NOW + 2);
+        contract.cancel_token_offer(offer_id);
+        assert!(contract.get_counter_offer(offer_id).is_none());
+        assert_eq!(contract.get_negotiation(offer_id).len(), 2);
+    }
+
+    #[test]
+    fn the_seller_pays_for_the_rounds() {
+        let (mut contract, offer_id) = offer();
+        let paid_storage = contract.storage_balance_of(alice());
+        counter(&mut contract, offer_id, 900, None);
+
+        assert!(contract.storage_balance_of(alice()).0 < paid_storage.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage paid")]
+    fn counter_offers_need_a_storage_deposit() {
+        let (mut contract, offer_id) = offer_without_seller_storage();
+        counter(&mut contract, offer_id, 900, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "A negotiation can have at most 20 rounds")]
+    fn negotiations_are_capped() {
+        let (mut contract, offer_id) = offer();
+        for price in 600..620 {
+            counter(&mut contract, offer_id, price, None);
+        }
+    }
+
+    #[test]
+    fn accepting_tops_up_the_offer_and_refunds_the_excess() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 800, None);
+
+        call(bob(), 400, NOW + 2);
+        contract.accept_counter_offer(offer_id);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 100)]);
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("800"));
+        assert!(contract.get_counter_offer(offer_id).is_none());
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), 800)]), NOW + 2);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), 776)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 8),
+                ("near".to_string(), account("commissions.near"), 16),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Offer and deposit must be greater than or equal to the counter price")]
+    fn accepting_needs_the_full_counter_price() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 800, None);
+
+        call(bob(), 299, NOW + 2);
+        contract.accept_counter_offer(offer_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Counter offer has expired")]
+    fn expired_counter_offers_cannot_be_accepted() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 800, Some(NOW + 2));
+
+        call(bob(), 300, NOW + 2);
+        contract.accept_counter_offer(offer_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the buyer can accept a counter offer")]
+    fn only_the_buyer_can_accept() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 800, None);
+
+        call(carol(), 300, NOW + 2);
+        contract.accept_counter_offer(offer_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Counter price must be greater than the offer. Accept the offer instead")]
+    fn counter_offers_have_to_ask_for_more() {
+        let (mut contract, offer_id) = offer();
+        counter(&mut contract, offer_id, 500, None);
+    }
+}
@@ -6,12 +6,90 @@ use crate::*;
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SaleArgs {
-    pub price: U128, // Sale price is in yoctonear
+    pub sale_conditions: SaleConditions, // Sale price for each token, in yoctonear for "near" or in the smallest unit of the token
     pub is_auction: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub start_time: Option<U64>, //Unix timestamp for when auction starts
+    pub start_time: Option<U64>, //Unix timestamp (in nanoseconds) for when the sale starts
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub end_time: Option<U64>, //Unix timestamp for when auction finishes
+    pub end_time: Option<U64>, //Unix timestamp (in nanoseconds) for when the sale finishes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension_window: Option<U64>, //bids this close (in nanoseconds) to the end time extend the auction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension_duration: Option<U64>, //how long (in nanoseconds) the end time is pushed forward by each extension
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_end_time: Option<U64>, //Unix timestamp (in nanoseconds) the auction can't be extended past
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve_price: Option<U128>, //lowest winning bid the seller will accept, separate from the starting price
+    #[serde(default)]
+    pub hide_reserve_price: bool, //keep the reserve price out of the views
+    #[serde(default)]
+    pub keep_if_reserve_not_met: bool, //keep the auction on the market to be relisted if the reserve isn't met
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_now_price: Option<U128>, //price that ends the auction right away, in the auction's token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_now_bid_threshold: Option<U128>, //keep buy now available until the highest bid reaches this, instead of until the first bid
+    #[serde(default)]
+    pub is_dutch_auction: bool, //the price drops from the price in sale_conditions to floor_price between start_time and end_time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub floor_price: Option<U128>, //lowest price of a dutch auction, reached at end_time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_step_interval: Option<U64>, //drop a dutch auction's price in steps this long (in nanoseconds) instead of linearly
+    #[serde(default)]
+    pub is_sealed_bid: bool, //bids are committed as hashes until end_time and revealed until reveal_end_time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reveal_end_time: Option<U64>, //Unix timestamp (in nanoseconds) for when the reveal phase of a sealed bid auction finishes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forfeit_penalty: Option<U128>, //amount kept from the deposit of a sealed bid that is never revealed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_bid_increment: Option<U128>, //absolute amount each bid has to beat the current one by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_bid_increment_bps: Option<u128>, //percentage (in basis points) of the current bid each bid has to beat it by
+    #[serde(default)]
+    pub is_second_price: bool, //the winner of the auction pays one increment above the second highest bid instead of their own bid
+    #[serde(default)]
+    pub relist_count: u32, //how many times the auction is relisted if it ends without bids
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relist_duration: Option<U64>, //how long (in nanoseconds) each relisted auction runs. Defaults to the original duration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relist_starting_price: Option<U128>, //lower starting price for the relisted auctions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relist_fixed_price: Option<U128>, //once there are no relists left, turn the auction into a fixed price sale at this price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<U64>, //how long (in nanoseconds) the sale runs, instead of start_time and end_time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_delay: Option<U64>, //how long (in nanoseconds) after the approval the sale starts when using duration. Defaults to 0
+}
+
+//struct for the msg that accepts an offer for a token instead of listing it
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptOfferArgs {
+    pub accept_offer: AcceptOffer,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptOffer {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offer_id: Option<u64>, //the token or collection offer to accept. Without it, the best offer for the token is accepted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_price: Option<U128>, //the lowest offer the owner accepts, in case the best offer changes before the approval lands
+}
+
+//struct for the msg that answers a token offer with a counter price instead of listing the token
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CounterOfferArgs {
+    pub counter_offer: CounterOfferMsg,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CounterOfferMsg {
+    pub offer_id: u64, //the token offer to answer
+    pub price: U128, //price the seller is willing to sell for, in yoctoNEAR
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<U64>, //Unix timestamp (in nanoseconds) the buyer can't accept the counter offer after
 }
 
 /*
@@ -19,7 +97,7 @@ pub struct SaleArgs {
     called, it will fire a cross contract call to this marketplace and this is the function
     that is invoked. 
 */
-trait NonFungibleTokenApprovalsReceiver {
+pub trait NonFungibleTokenApprovalsReceiver {
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
@@ -33,7 +111,6 @@ trait NonFungibleTokenApprovalsReceiver {
 #[near_bindgen]
 impl NonFungibleTokenApprovalsReceiver for Contract {
     /// where we add the sale because we know nft owner can only call nft_approve
-
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
@@ -58,6 +135,34 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             "owner_id should be signer_id"
         );
 
+        //the owner can sell the token to an offer instead of listing it. The token never goes on the market
+        //so this doesn't need any storage
+        if let Ok(AcceptOfferArgs { accept_offer }) = near_sdk::serde_json::from_str::<AcceptOfferArgs>(&msg) {
+            self.internal_accept_offer(
+                owner_id,
+                approval_id,
+                nft_contract_id,
+                token_id,
+                accept_offer.offer_id,
+                accept_offer.min_price,
+            );
+            return;
+        }
+
+        //the owner can answer a token offer with a counter price. The buyer accepts it with accept_counter_offer
+        if let Ok(CounterOfferArgs { counter_offer }) = near_sdk::serde_json::from_str::<CounterOfferArgs>(&msg) {
+            self.internal_counter_token_offer(
+                owner_id,
+                approval_id,
+                nft_contract_id,
+                token_id,
+                counter_offer.offer_id,
+                counter_offer.price,
+                counter_offer.expires_at,
+            );
+            return;
+        }
+
         //we need to enforce that the user has enough storage for 1 EXTRA sale.  
 
         //get the storage for a sale. dot 0 converts from U128 to u128
@@ -65,7 +170,7 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
         //get the total storage paid by the owner
         let owner_paid_storage = self.storage_deposits.get(&signer_id).unwrap_or(0);
         //get the storage required which is simply the storage for the number of sales they have + 1 
-        let signer_storage_required = (self.get_supply_by_owner_id(signer_id).0 + 1) as u128 * storage_amount;
+        let signer_storage_required = (self.get_supply_by_owner_id(signer_id.clone()).0 + self.internal_offer_count(&signer_id) + 1) as u128 * storage_amount;
         
         //make sure that the total paid is >= the required storage
         assert!(
@@ -74,23 +179,28 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             owner_paid_storage, signer_storage_required / STORAGE_PER_SALE, STORAGE_PER_SALE
         );
 
-        let SaleArgs {
-            price,
-            is_auction,
-            start_time,
-            end_time
-        } = near_sdk::serde_json::from_str(&msg).expect("Not valid MarketArgs");
+        let sale_args: SaleArgs = near_sdk::serde_json::from_str(&msg).expect("Not valid MarketArgs");
+
+        //make sure the sale is priced in at least one token and that the marketplace accepts all of them
+        assert!(!sale_args.sale_conditions.is_empty(), "Sale conditions cannot be empty");
+        for ft_token_id in sale_args.sale_conditions.keys() {
+            assert!(
+                self.ft_token_ids.contains(ft_token_id),
+                "Token {} is not accepted by the marketplace",
+                ft_token_id
+            );
+        }
         
         self.internal_add_market_data(
             owner_id,
             approval_id,
-            nft_contract_id,
-            token_id,
-            price,
-            start_time,
-            end_time,
-            is_auction,
+            nft_contract_id.clone(),
+            token_id.clone(),
+            sale_args,
         );
         //Extra functionality that populates collections necessary for the view calls 
+
+        //a fixed price sale that meets an open buy order is bought right away
+        self.internal_fill_buy_order(nft_contract_id, token_id);
     }
 }
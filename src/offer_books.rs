@@ -0,0 +1,76 @@
+use crate::*;
+use crate::internal::assert_timestamp_in_nanoseconds;
+
+//offers and buy orders by price. The keys come from offer_book_key and the values are offer IDs
+pub type OfferBook = TreeMap<(u128, u64), u64>;
+
+//offer books are ordered by this key, so iterating one in reverse gives the highest price first and the oldest offer
+//first for equal prices
+pub(crate) fn offer_book_key(price: u128, offer_id: u64) -> (u128, u64) {
+    (price, u64::MAX - offer_id)
+}
+
+//the most offers or buy orders looked at when searching a book for the best one. Expired ones found on the way are
+//removed and refunded, so this keeps a book full of them from running the call out of gas. The rest can be removed
+//with the cancel methods
+pub(crate) const MAX_OFFERS_SCANNED: usize = 10;
+
+//makes sure an expiry passed in by a user is a Unix timestamp in nanoseconds that hasn't passed yet
+pub(crate) fn assert_valid_expiry(expires_at: Option<U64>) {
+    assert_timestamp_in_nanoseconds("expires_at", expires_at);
+    if let Some(expires_at) = expires_at {
+        assert!(expires_at.0 > env::block_timestamp(), "expires_at has already passed");
+    }
+}
+
+//whether something with the given expiry (offers, buy orders and counter offers) has expired. Without one it never does
+pub(crate) fn is_expired(expires_at: Option<U64>) -> bool {
+    expires_at.map(|expires_at| expires_at.0 <= env::block_timestamp()).unwrap_or(false)
+}
+
+//adds an offer to the book under the given key. If there is no book yet, one is created with the given prefix
+pub(crate) fn insert_into_book<K: BorshSerialize>(
+    books: &mut LookupMap<K, OfferBook>,
+    key: &K,
+    prefix: StorageKey,
+    price: u128,
+    offer_id: u64,
+) {
+    let mut book = books.get(key).unwrap_or_else(|| TreeMap::new(prefix.try_to_vec().unwrap()));
+    book.insert(&offer_book_key(price, offer_id), &offer_id);
+    books.insert(key, &book);
+}
+
+//removes an offer from the book under the given key. The book is removed once it's empty
+pub(crate) fn remove_from_book<K: BorshSerialize>(books: &mut LookupMap<K, OfferBook>, key: &K, price: u128, offer_id: u64) {
+    let mut book = books.get(key).expect("No offer book");
+    book.remove(&offer_book_key(price, offer_id));
+    if book.is_empty() {
+        books.remove(key);
+    } else {
+        books.insert(key, &book);
+    }
+}
+
+//adds an offer to the set of offers of the given account. If there is no set yet, one is created with the given prefix
+pub(crate) fn insert_into_account_set(
+    sets: &mut LookupMap<AccountId, UnorderedSet<u64>>,
+    account_id: &AccountId,
+    prefix: StorageKey,
+    offer_id: u64,
+) {
+    let mut set = sets.get(account_id).unwrap_or_else(|| UnorderedSet::new(prefix.try_to_vec().unwrap()));
+    set.insert(&offer_id);
+    sets.insert(account_id, &set);
+}
+
+//removes an offer from the set of offers of the given account. The set is removed once it's empty
+pub(crate) fn remove_from_account_set(sets: &mut LookupMap<AccountId, UnorderedSet<u64>>, account_id: &AccountId, offer_id: u64) {
+    let mut set = sets.get(account_id).expect("No offers for account");
+    set.remove(&offer_id);
+    if set.is_empty() {
+        sets.remove(account_id);
+    } else {
+        sets.insert(account_id, &set);
+    }
+}
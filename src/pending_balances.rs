@@ -0,0 +1,135 @@
+use crate::*;
+use crate::internal::{hash_account_id, near_token_id, pay_account};
+use near_sdk::{is_promise_success, log};
+
+#[near_bindgen]
+impl Contract {
+
+    //withdraws the caller's pending balance in the given fungible token (NEAR if none is passed in).
+    //pending balances are refunds and payouts that couldn't be sent to the account when they were due, and the
+    //deposits of sealed bids once their auction is over
+    #[payable]
+    pub fn withdraw_pending(&mut self, ft_token_id: Option<FungibleTokenId>) {
+        //make sure the user attaches exactly 1 yoctoNEAR for security purposes.
+        //this will redirect them to the NEAR wallet (or requires a full access key). 
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let ft_token_id = ft_token_id.unwrap_or_else(near_token_id);
+
+        //get the balances of the account. If there are none, panic
+        let mut balances = self.pending_balances.get(&account_id).expect("No pending balance");
+        let amount = balances.remove(&ft_token_id).expect("No pending balance");
+
+        //if the account has no other pending balances, we remove it from the map
+        if balances.is_empty() {
+            self.pending_balances.remove(&account_id);
+        } else {
+            self.pending_balances.insert(&account_id, &balances);
+        }
+
+        //if this payment fails too, the balance is credited back to the account
+        pay_account(&ft_token_id, account_id, amount);
+    }
+
+    /// views
+    //returns the pending balances of an account for every fungible token ("near" for NEAR)
+    pub fn get_pending_balances(&self, account_id: AccountId) -> Vec<(FungibleTokenId, U128)> {
+        self.pending_balances
+            .get(&account_id)
+            .map(|balances| balances.iter().map(|(ft_token_id, amount)| (ft_token_id, U128(amount))).collect())
+            .unwrap_or_default()
+    }
+
+    //private method used to resolve the promise of every payment the market makes. If the payment
+    //failed, the amount is credited to the receiver's pending balance so they can withdraw it later
+    #[private]
+    pub fn resolve_payment(&mut self, receiver_id: AccountId, ft_token_id: FungibleTokenId, amount: U128) {
+        if !is_promise_success() {
+            log!("Payment of {} {} to {} failed, adding it to the pending balance", amount.0, ft_token_id, receiver_id);
+            self.internal_credit_pending(&receiver_id, &ft_token_id, amount.0);
+        }
+    }
+}
+
+impl Contract {
+    //internal method for adding to an account's pending balance in the given fungible token
+    pub(crate) fn internal_credit_pending(&mut self, account_id: &AccountId, ft_token_id: &FungibleTokenId, amount: u128) {
+        //get the balances of the account. If there are none, we create a new empty map
+        let mut balances = self.pending_balances.get(account_id).unwrap_or_else(|| {
+            UnorderedMap::new(
+                StorageKey::PendingBalancesInner {
+                    //we get a new unique prefix for the collection by hashing the account
+                    account_id_hash: hash_account_id(account_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+
+        let balance = balances.get(ft_token_id).unwrap_or(0);
+        balances.insert(ft_token_id, &(balance + amount));
+        self.pending_balances.insert(account_id, &balances);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::PromiseResult;
+
+    fn failed_payment(contract: &mut Contract, receiver_id: AccountId, ft_token_id: AccountId, amount: u128) {
+        callback(PromiseResult::Failed, NOW);
+        contract.resolve_payment(receiver_id, ft_token_id, U128(amount));
+    }
+
+    #[test]
+    fn failed_payments_are_credited_to_the_pending_balance() {
+        let mut contract = new_market();
+        failed_payment(&mut contract, bob(), usdc(), 10);
+        failed_payment(&mut contract, bob(), usdc(), 5);
+        failed_payment(&mut contract, bob(), near_token_id(), 7);
+
+        let mut balances = contract.get_pending_balances(bob());
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(balances, vec![(near_token_id(), U128(7)), (usdc(), U128(15))]);
+    }
+
+    #[test]
+    fn successful_payments_are_not_credited() {
+        let mut contract = new_market();
+        callback(PromiseResult::Successful(vec![]), NOW);
+        contract.resolve_payment(bob(), usdc(), U128(10));
+
+        assert!(contract.get_pending_balances(bob()).is_empty());
+    }
+
+    #[test]
+    fn withdrawing_pays_the_balance_and_clears_it() {
+        let mut contract = new_market();
+        failed_payment(&mut contract, bob(), usdc(), 10);
+        failed_payment(&mut contract, bob(), near_token_id(), 7);
+
+        call(bob(), 1, NOW);
+        contract.withdraw_pending(Some(usdc()));
+        assert_eq!(payments(), vec![("usdc.near".to_string(), bob(), 10)]);
+        //the withdrawal is credited back if it fails too
+        assert_eq!(scheduled_call("resolve_payment")["amount"], "10");
+        assert_eq!(contract.get_pending_balances(bob()), vec![(near_token_id(), U128(7))]);
+
+        call(bob(), 1, NOW);
+        contract.withdraw_pending(None);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 7)]);
+        assert!(contract.get_pending_balances(bob()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "No pending balance")]
+    fn nothing_to_withdraw() {
+        let mut contract = new_market();
+
+        call(bob(), 1, NOW);
+        contract.withdraw_pending(None);
+    }
+}
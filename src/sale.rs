@@ -1,8 +1,10 @@
 use crate::*;
 use near_sdk::promise_result_as_success;
 use near_sdk::log;
+use crate::internal::{near_token_id, pay_account};
 
-const MIN_BID_INCREMENT : u128 = 10_000_000_000_000_000_000_000; // 0.01 N
+//bid increment for NEAR auctions that don't set their own
+const DEFAULT_MIN_BID_INCREMENT : u128 = 10_000_000_000_000_000_000_000; // 0.01 N
 
 //struct that holds important information about each sale on the market
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -12,11 +14,237 @@ pub struct Sale {
     pub approval_id: u64,
     pub nft_contract_id: String,
     pub token_id: String,
-    pub price: u128,
+    pub sale_conditions: SaleConditions, //price of the sale for each fungible token it can be bought with
     pub bids: Option<Bids>,
     pub is_auction: bool,
-    pub start_time: Option<u64>, //Unix timestamp for when auction starts
-    pub end_time: Option<u64>, //Unix timestamp for when auction finishes
+    pub start_time: Option<u64>, //Unix timestamp (in nanoseconds) for when the sale starts
+    pub end_time: Option<u64>, //Unix timestamp (in nanoseconds) for when the sale finishes, including any anti-sniping extensions
+    pub extension_window: Option<u64>, //bids this close (in nanoseconds) to the end time extend the auction
+    pub extension_duration: Option<u64>, //how long (in nanoseconds) the end time is pushed forward by each extension
+    pub max_end_time: Option<u64>, //Unix timestamp (in nanoseconds) the auction can't be extended past
+    //lowest winning bid the seller will accept. This is never returned by the views, use get_reserve_info instead.
+    //(like everything else in contract storage it can still be read straight from the state)
+    #[serde(skip)]
+    pub reserve_price: Option<u128>,
+    pub hide_reserve_price: bool, //whether get_reserve_info should leave out the reserve price
+    pub keep_if_reserve_not_met: bool, //keep the auction on the market instead of removing it if the reserve isn't met
+    pub is_ended: bool, //the auction ended without meeting its reserve and is only kept on the market for the seller to relist it
+    pub buy_now_price: Option<u128>, //price that ends the auction right away, in the auction's token
+    pub buy_now_bid_threshold: Option<u128>, //buy now stays available until the highest bid reaches this. Without it, only until the first bid
+    pub is_dutch_auction: bool, //the price drops from the starting price to floor_price between start_time and end_time
+    pub floor_price: Option<u128>, //lowest price of a dutch auction, reached at end_time
+    pub price_step_interval: Option<u64>, //if set, a dutch auction's price drops in steps this long (in nanoseconds) instead of linearly
+    pub is_sealed_bid: bool, //bids are committed as hashes until end_time and revealed until reveal_end_time
+    pub reveal_end_time: Option<u64>, //Unix timestamp (in nanoseconds) for when the reveal phase of a sealed bid auction finishes
+    pub forfeit_penalty: Option<u128>, //amount kept from the deposit of a sealed bid that is never revealed, paid to the seller
+    pub min_bid_increment: Option<u128>, //absolute amount each bid has to beat the current one by
+    pub min_bid_increment_bps: Option<u128>, //percentage (in basis points) of the current bid each bid has to beat it by
+    //maximum the current bidder escrowed with a proxy bid. The market bids for them up to this amount.
+    //This is never returned by the views so neither the seller nor other bidders can see it
+    #[serde(skip)]
+    pub max_bid: Option<u128>,
+    pub is_second_price: bool, //the winner pays one increment above the second highest bid instead of their own bid
+    //highest bid (or proxy maximum) of anyone other than the current bidder. Hidden like max_bid since it can be a proxy maximum
+    #[serde(skip)]
+    pub runner_up_bid: Option<u128>,
+    pub relists_left: u32, //how many more times the auction is relisted if it ends without bids
+    pub relist_duration: Option<u64>, //how long (in nanoseconds) each relisted auction runs. Without it, as long as the original
+    pub relist_starting_price: Option<u128>, //starting price of the relisted auctions. Without it, the original starting price
+    pub relist_fixed_price: Option<u128>, //once there are no relists left, the auction becomes a fixed price sale at this price
+    pub auction_seq: u64, //which of the token's auctions this is (starting at 1), for looking up its bid history. 0 for other sales
+}
+
+impl Sale {
+    //a fixed price sale for a token that is sold as soon as it's approved, like when its owner accepts an offer.
+    //these sales only carry the owner and approval ID into resolve_purchase. It's also the sale an auction becomes
+    //once it runs out of relists
+    pub(crate) fn new_fixed_price(
+        owner_id: AccountId,
+        approval_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        ft_token_id: FungibleTokenId,
+        price: u128,
+    ) -> Self {
+        let mut sale_conditions = SaleConditions::new();
+        sale_conditions.insert(ft_token_id, U128(price));
+
+        Sale {
+            owner_id,
+            approval_id,
+            nft_contract_id: nft_contract_id.into(),
+            token_id,
+            sale_conditions,
+            bids: None,
+            is_auction: false,
+            start_time: None,
+            end_time: None,
+            extension_window: None,
+            extension_duration: None,
+            max_end_time: None,
+            reserve_price: None,
+            hide_reserve_price: false,
+            keep_if_reserve_not_met: false,
+            is_ended: false,
+            buy_now_price: None,
+            buy_now_bid_threshold: None,
+            is_dutch_auction: false,
+            floor_price: None,
+            price_step_interval: None,
+            is_sealed_bid: false,
+            reveal_end_time: None,
+            forfeit_penalty: None,
+            min_bid_increment: None,
+            min_bid_increment_bps: None,
+            max_bid: None,
+            is_second_price: false,
+            runner_up_bid: None,
+            relists_left: 0,
+            relist_duration: None,
+            relist_starting_price: None,
+            relist_fixed_price: None,
+            auction_seq: 0,
+        }
+    }
+
+    //auctions are priced in a single token. This returns that token and the starting price in it
+    pub(crate) fn auction_condition(&self) -> (FungibleTokenId, u128) {
+        let (ft_token_id, price) = self.sale_conditions.iter().next().expect("Auction has no price");
+        (ft_token_id.clone(), price.0)
+    }
+
+    //the price of a dutch auction at the given timestamp. It drops from the starting price at start_time
+    //to the floor price at end_time, either linearly or once every price_step_interval
+    pub(crate) fn dutch_auction_price_at(&self, timestamp: u64) -> u128 {
+        let (_, start_price) = self.auction_condition();
+        let floor_price = self.floor_price.unwrap();
+        let start_time = self.start_time.unwrap();
+        let end_time = self.end_time.unwrap();
+
+        if timestamp <= start_time {
+            return start_price;
+        }
+        if timestamp >= end_time {
+            return floor_price;
+        }
+
+        let mut elapsed = timestamp - start_time;
+        if let Some(price_step_interval) = self.price_step_interval {
+            elapsed -= elapsed % price_step_interval;
+        }
+
+        //the drop times the elapsed time can be more than u128::MAX for large prices and long auctions, so the drop is split
+        //into whole multiples of the duration and a remainder. Since elapsed < duration < 2^64, neither part can overflow
+        let price_drop = start_price - floor_price;
+        let duration = u128::from(end_time - start_time);
+        let elapsed = u128::from(elapsed);
+        start_price - (price_drop / duration * elapsed + price_drop % duration * elapsed / duration)
+    }
+
+    //the minimum amount the next bid has to beat the given price by. Without a seller set increment, NEAR auctions
+    //use 0.01 N and fungible token auctions only have to beat the price
+    pub(crate) fn min_bid_increment_on(&self, price: u128) -> u128 {
+        if let Some(min_bid_increment) = self.min_bid_increment {
+            return min_bid_increment;
+        }
+        if let Some(min_bid_increment_bps) = self.min_bid_increment_bps {
+            return (price * min_bid_increment_bps / 10_000).max(1);
+        }
+
+        let (ft_token_id, _) = self.auction_condition();
+        if ft_token_id.as_str() == NEAR_TOKEN_ID { DEFAULT_MIN_BID_INCREMENT } else { 1 }
+    }
+
+    //the current bidder and the amount the market holds for them. For proxy bids this is their maximum, otherwise their bid
+    pub(crate) fn current_bid_escrow(&self) -> Option<(AccountId, u128)> {
+        self.bids
+            .as_ref()
+            .and_then(|bids| bids.last())
+            .map(|bid| (bid.bidder_id.clone(), self.max_bid.unwrap_or(bid.price.0)))
+    }
+
+    //the price a proxy bid with the given maximum bids at. It never goes past the maximum and is raised to
+    //the reserve price if the maximum covers it
+    pub(crate) fn proxy_bid_price(&self, price: u128, max_bid: u128) -> u128 {
+        let price = price.min(max_bid);
+        match self.reserve_price {
+            Some(reserve_price) if max_bid >= reserve_price => price.max(reserve_price),
+            _ => price,
+        }
+    }
+
+    //the price the winner of the auction pays. For second price auctions this is one increment above the runner up's bid
+    //(the starting price without one), raised to the reserve price and never more than the winning bid
+    pub(crate) fn winning_price(&self, winning_amount: u128, runner_up_amount: Option<u128>) -> u128 {
+        if !self.is_second_price {
+            return winning_amount;
+        }
+
+        let price = match runner_up_amount {
+            Some(runner_up_amount) => runner_up_amount + self.min_bid_increment_on(runner_up_amount),
+            None => self.auction_condition().1,
+        };
+        price.max(self.reserve_price.unwrap_or(0)).min(winning_amount)
+    }
+
+    //the time an auction can be settled after: the reveal end time for sealed bid auctions and the end time for other auctions
+    pub(crate) fn settlement_time(&self) -> Option<u64> {
+        if !self.is_auction {
+            return None;
+        }
+        if self.is_sealed_bid { self.reveal_end_time } else { self.end_time }
+    }
+
+    //whether the auction can currently be bought at its buy now price
+    pub(crate) fn is_buy_now_available(&self) -> bool {
+        if self.buy_now_price.is_none() {
+            return false;
+        }
+
+        match self.bids.as_ref().and_then(|bids| bids.last()) {
+            None => true,
+            Some(current_bid) => self
+                .buy_now_bid_threshold
+                .map(|threshold| current_bid.price.0 < threshold)
+                .unwrap_or(false),
+        }
+    }
+
+    //whether this is an auction that is over and has no bids left
+    pub(crate) fn is_ended_without_bids(&self) -> bool {
+        self.is_auction
+            && self.end_time.map(|end_time| end_time < env::block_timestamp()).unwrap_or(false)
+            && self.bids.as_ref().map(|bids| bids.is_empty()).unwrap_or(true)
+    }
+
+    //whether this is a fixed price sale or dutch auction that is past its end time. Anyone can remove these from the market
+    pub(crate) fn is_expired_listing(&self) -> bool {
+        !self.is_auction
+            && self.end_time.map(|end_time| end_time <= env::block_timestamp()).unwrap_or(false)
+    }
+
+    //makes sure a fixed price sale can be bought right now, i.e. it's past its start time and before its end time
+    pub(crate) fn assert_listing_open(&self) {
+        let current_time: u64 = env::block_timestamp();
+        if let Some(start_time) = self.start_time {
+            assert!(start_time <= current_time, "Cannot buy before the sale starts");
+        }
+        assert!(!self.is_expired_listing(), "Cannot buy since the sale is over");
+    }
+
+    //whether the highest bid has reached the reserve price. Auctions without a reserve always meet it
+    pub(crate) fn is_reserve_met(&self) -> bool {
+        let reserve_price = match self.reserve_price {
+            Some(reserve_price) => reserve_price,
+            None => return true,
+        };
+
+        self.bids
+            .as_ref()
+            .and_then(|bids| bids.last())
+            .map(|bid| bid.price.0 >= reserve_price)
+            .unwrap_or(false)
+    }
 }
 
 #[near_bindgen]
@@ -28,11 +256,11 @@ impl Contract {
         //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
         assert_one_yocto();
         //get the sale object as the return value from removing the sale internally
-        let sale = self.internal_remove_sale(nft_contract_id.into(), token_id);
+        let sale = self.internal_remove_sale(nft_contract_id, token_id);
         //get the predecessor of the call and make sure they're either sale owner or smart contract owner
         let caller_id = env::predecessor_account_id();
-        //if this fails, the remove sale will revert
-        if caller_id!=sale.owner_id && caller_id!=self.owner_id{
+        //if this fails, the remove sale will revert. Fixed price sales that are over can be removed by anyone
+        if caller_id!=sale.owner_id && caller_id!=self.owner_id && !sale.is_expired_listing(){
             env::panic_str("Must be either sale owner or owner of smart contract!");
         }
 
@@ -42,43 +270,58 @@ impl Contract {
           1.smart contract owner can remove the auction any time, no constraints. (will only be exercised in case of tokens where marketplace is not approved anymore)
           2.token owner can remove it any time if it has no bids else no removal allowed after end_time if there are bids.
         */
-        if sale.is_auction && sale.bids.is_some(){
+        if sale.is_sealed_bid {
+
+            let (ft_token_id, _) = sale.auction_condition();
+            let contract_and_token_id = format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+
+            if self.has_sealed_bids(&contract_and_token_id) {
+                if caller_id==sale.owner_id {
+                    assert!(current_time < sale.end_time.unwrap(), "Cannot remove auction now since the end_time has been crossed. Consider ending the auction instead.");
+                }
+
+                // refund every sealed bid in full
+                self.internal_refund_sealed_bids(&contract_and_token_id, &ft_token_id);
+            }
+        }
+        else if sale.is_auction {
             
-            let bids= sale.bids.unwrap();
+            let (ft_token_id, _) = sale.auction_condition();
             
-            if bids.len()>0{
+            if let Some((bidder_id, escrow)) = sale.current_bid_escrow() {
                 let end_time=sale.end_time;
                 
                 if  caller_id==sale.owner_id {
                     assert!(current_time < end_time.unwrap(), "Cannot remove auction now since the end_time has been crossed. Consider ending the auction instead.");
                 }
 
-                let current_bid = &bids[bids.len() - 1];
-                // refund
-                Promise::new(current_bid.bidder_id.clone()).transfer(current_bid.price.0);
+                // refund everything held for the current bid in the token the auction is priced in
+                pay_account(&ft_token_id, bidder_id, escrow);
             }
         } 
     }
 
-    //updates the price for a sale on the market
+    //updates the price for a sale on the market in the given fungible token (NEAR if none is passed in).
+    //if the sale couldn't be bought in that token before, this adds it as a new currency for the sale
     #[payable]
     pub fn update_price(
         &mut self,
         nft_contract_id: AccountId,
         token_id: String,
+        ft_token_id: Option<FungibleTokenId>,
         amount: U128,
     ) {
         //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
         assert_one_yocto();
         
         //create the unique sale ID from the nft contract and token
-        let contract_id: AccountId = nft_contract_id.into();
+        let contract_id: AccountId = nft_contract_id;
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
         
         //get the sale object from the unique sale ID. If there is no token, panic. 
         let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
 
-        if sale.is_auction{
+        if sale.is_auction || sale.is_dutch_auction {
             env::panic_str("Sorry, cannot update an auction");
         }
 
@@ -88,9 +331,17 @@ impl Contract {
             sale.owner_id,
             "Must be sale owner"
         );
+
+        //make sure the marketplace accepts the token the price is being set in
+        let ft_token_id = ft_token_id.unwrap_or_else(near_token_id);
+        assert!(
+            self.ft_token_ids.contains(&ft_token_id),
+            "Token {} is not accepted by the marketplace",
+            ft_token_id
+        );
         
-        //set the price equal to the passed in amount
-        sale.price = amount.into();
+        //set the price for the token equal to the passed in amount
+        sale.sale_conditions.insert(ft_token_id, amount);
         //insert the sale back into the map for the unique sale ID
         self.sales.insert(&contract_and_token_id, &sale);
     }
@@ -100,96 +351,38 @@ impl Contract {
         let deposit = env::attached_deposit();
         assert!(deposit > 0, "Attached deposit must be greater than 0");
 
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
-        let mut sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
-
-        if sale.is_auction {
-            let current_time: u64 = env::block_timestamp();
-            let start_time=sale.start_time;
-            let end_time=sale.end_time;
-
-            assert!( start_time.unwrap() < current_time, "Cannot bid before auction starts");
-            assert!( current_time < end_time.unwrap() ,"Cannot bid since auction is over" );
-        }
-        else{
-            env::panic_str("Sale should be an auction");
-        }
-
-        let bidder_id = env::predecessor_account_id();
-        assert_ne!(sale.owner_id, bidder_id, "Cannot bid on your own sale.");
-
-        let new_bid = Bid {
-            bidder_id: bidder_id.clone(),
-            price: U128(deposit),
-        };
-
-        let mut bids = sale.bids.unwrap_or(Vec::new());
-
-        if !bids.is_empty() {
-            let current_bid = &bids[bids.len() - 1];
-
-            assert!(
-                deposit >= (current_bid.price.0 + MIN_BID_INCREMENT),
-                "Can't pay less than or equal to current bid price + increment (0.01 N) : {:?}",
-                current_bid.price
-            );
-
-            assert!(
-                deposit > sale.price,
-                "Can't pay less than or equal to starting price: {:?}",
-                U128(sale.price)
-            );
-
-            // refund
-            Promise::new(current_bid.bidder_id.clone()).transfer(current_bid.price.0);
+        //bids in fungible tokens come in through ft_transfer_call instead
+        self.internal_add_bid(
+            nft_contract_id,
+            token_id,
+            near_token_id(),
+            env::predecessor_account_id(),
+            deposit,
+            false,
+        );
+    }
 
-            // always keep 1 bid for now
-            bids.remove(bids.len() - 1);
-        } else {
-            assert!(
-                deposit >= (sale.price + MIN_BID_INCREMENT),
-                "Can't pay less than or equal to starting price + increment (0.01 N): {}",
-                sale.price
-            );
-        }
+    //places a proxy bid on a NEAR auction. The deposit is the most the bidder is willing to pay and is held by the market,
+    //which bids for them one increment at a time as others bid. Whatever is left above the winning price is refunded at the end
+    #[payable]
+    pub fn add_proxy_bid(&mut self, nft_contract_id: AccountId, token_id: String){
+        let deposit = env::attached_deposit();
+        assert!(deposit > 0, "Attached deposit must be greater than 0");
 
-        bids.push(new_bid);
-        sale.bids = Some(bids);
-        self.sales.insert(&contract_and_token_id, &sale);
+        //proxy bids in fungible tokens come in through ft_transfer_call with "proxy" in the msg instead
+        self.internal_add_bid(
+            nft_contract_id,
+            token_id,
+            near_token_id(),
+            env::predecessor_account_id(),
+            deposit,
+            true,
+        );
     }
 
     #[payable]
     pub fn end_auction(&mut self, nft_contract_id: AccountId, token_id: String){
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
-        let sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
-
-        if sale.is_auction {
-            let current_time: u64 = env::block_timestamp();
-            let end_time=sale.end_time;
-
-            assert!( Some(current_time) > end_time, "Cannot end before end_time mentioned for the auction");
-        }
-        else{
-            env::panic_str("Sale should be an auction");
-        }
-
-        let bids = sale.bids.unwrap_or(Vec::new());
-
-        if !bids.is_empty() {
-            let current_bid = &bids[bids.len() - 1];
-            let buyer_id= current_bid.bidder_id.clone();
-            self.process_purchase(
-                contract_id,
-                token_id,
-                current_bid.price,
-                buyer_id,
-            );
-        }
-        else{
-            self.internal_remove_sale(contract_id, token_id);
-        }
+        self.internal_end_auction(nft_contract_id, token_id, None);
     }
 
     #[payable]
@@ -199,7 +392,7 @@ impl Contract {
         assert!(deposit > 0, "Attached deposit must be greater than 0");
 
         //convert the nft_contract_id from a AccountId to an AccountId
-        let contract_id: AccountId = nft_contract_id.into();
+        let contract_id: AccountId = nft_contract_id;
         //get the unique sale ID (contract + DELIMITER + token ID)
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
         
@@ -210,9 +403,28 @@ impl Contract {
         let buyer_id = env::predecessor_account_id();
         assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
         
-        assert!(sale.is_auction==false, "Please use add_bid function to bid on this auction item!");
+        //paying for an auction buys it at its buy now price. Bids have to go through add_bid
+        if sale.is_auction {
+            self.internal_buy_now(contract_id, token_id, near_token_id(), buyer_id, deposit);
+            return;
+        }
+
+        //a dutch auction is bought at its current price and anything paid above that is refunded
+        if sale.is_dutch_auction {
+            self.internal_buy_dutch_auction(contract_id, token_id, near_token_id(), buyer_id, deposit);
+            return;
+        }
+
+        //get the price of the sale in NEAR. Sales that are only priced in fungible tokens have to be bought
+        //through ft_transfer_call on one of those tokens
+        let price = sale
+            .sale_conditions
+            .get(&near_token_id())
+            .expect("This sale is not priced in NEAR. Please use ft_transfer_call on one of its tokens to buy it!")
+            .0;
 
-        let price = sale.price;
+        //make sure the sale has started and isn't over
+        sale.assert_listing_open();
 
         //make sure the deposit is greater than the price
         assert!(deposit >= price, "Attached deposit must be greater than or equal to the current price: {:?}", price);
@@ -221,8 +433,10 @@ impl Contract {
         self.process_purchase(
             contract_id,
             token_id,
+            near_token_id(),
             U128(deposit),
             buyer_id,
+            None,
         );
     }
 
@@ -233,39 +447,16 @@ impl Contract {
         &mut self,
         nft_contract_id: AccountId,
         token_id: String,
+        ft_token_id: FungibleTokenId,
         price: U128,
         buyer_id: AccountId,
+        keeper_id: Option<AccountId>,
     ) -> Promise {
         //get the sale object by removing the sale
-        let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
-
-        //initiate a cross contract call to the nft contract. This will transfer the token to the buyer and return
-        //a payout object used for the market to distribute funds to the appropriate accounts.
-        ext_contract::nft_transfer_payout(
-            buyer_id.clone(), //purchaser (person to transfer the NFT to)
-            token_id, //token ID to transfer
-            sale.approval_id, //market contract's approval ID in order to transfer the token on behalf of the owner
-            "payout from market".to_string(), //memo (to include some context)
-            /*
-                the price that the token was purchased for. This will be used in conjunction with the royalty percentages
-                for the token in order to determine how much money should go to which account. 
-            */
-            price,
-			10, //the maximum amount of accounts the market can payout at once (this is limited by GAS)
-            nft_contract_id, //contract to initiate the cross contract call to
-            1, //yoctoNEAR to attach to the call
-            GAS_FOR_NFT_TRANSFER, //GAS to attach to the call
-        )
-        //after the transfer payout has been initiated, we resolve the promise by calling our own resolve_purchase function. 
-        //resolve purchase will take the payout object returned from the nft_transfer_payout and actually pay the accounts
-        .then(ext_self::resolve_purchase(
-            buyer_id, //the buyer and price are passed in incase something goes wrong and we need to refund the buyer
-            price,
-            sale,
-            env::current_account_id(), //we are invoking this function on the current contract
-            NO_DEPOSIT, //don't attach any deposit
-            GAS_FOR_ROYALTIES, //GAS attached to the call to payout royalties
-        ))
+        let sale = self.internal_remove_sale(nft_contract_id, token_id);
+
+        //transfer the token to the buyer and pay out royalties and fees
+        self.internal_transfer_and_pay(sale, ft_token_id, price, buyer_id, keeper_id)
     }
 
     /*
@@ -277,8 +468,10 @@ impl Contract {
     pub fn resolve_purchase(
         &mut self,
         buyer_id: AccountId,
+        ft_token_id: FungibleTokenId,
         price: U128,
         sale: Sale,
+        keeper_id: Option<AccountId>,
     ) -> U128 {
         // checking for payout information returned from the nft_transfer_payout method
         let payout_option = promise_result_as_success().and_then(|value| {
@@ -322,33 +515,62 @@ impl Contract {
             payout_option
         //if the payout option was None, we refund the buyer for the price they payed and return
         } else {
-            Promise::new(buyer_id).transfer(u128::from(price));
+            //fungible tokens for a fixed price sale are refunded by returning the price, which ft_resolve_transfer
+            //sends back to the buyer. Auction payments (winning bids and buy now) are already held by the market so they're sent back directly
+            if ft_token_id.as_str() == NEAR_TOKEN_ID || sale.is_auction {
+                pay_account(&ft_token_id, buyer_id, u128::from(price));
+            }
             // leave function and return the price that was refunded
             return price;
         };
 
-        let charges = price.0 * self.charges / 10_000u128;
-        let commissions = price.0 * self.commissions / 10_000u128;
+        //the fee table for the collection, which falls back to the global one
+        let nft_contract_id: AccountId = sale.nft_contract_id.parse().unwrap();
+        let fee_table = self.internal_effective_fees(&nft_contract_id);
+        let mut fees: Vec<(AccountId, u128)> = fee_table
+            .into_iter()
+            .map(|entry| (entry.account_id, price.0 * entry.bps / 10_000u128))
+            .collect();
+        let total_fees: u128 = fees.iter().map(|(_, fee)| fee).sum();
+
+        //the keeper that settled the auction (if any) is paid out of the fees, taken from the entries in order
+        let keeper_fee = match keeper_id {
+            Some(_) => (price.0 * self.keeper_fee / 10_000u128).min(total_fees),
+            None => 0,
+        };
+        let mut keeper_fee_left = keeper_fee;
+        for (_, fee) in fees.iter_mut() {
+            let taken = keeper_fee_left.min(*fee);
+            *fee -= taken;
+            keeper_fee_left -= taken;
+        }
 
-        log!("Fees that should be going is: {}", charges + commissions);
+        log!("Fees that should be going is: {}", total_fees);
         
-        // 2 accounts : commission and charges
-        // NEAR payouts
+        // every account in the fee table is paid out of the seller's share. Fees are credited to the
+        // fee ledger for the recipients to withdraw, while payouts are sent in the currency the sale was bought with
         for (receiver_id, amount) in payout {
             if receiver_id == sale.owner_id {
-                Promise::new(receiver_id).transfer(amount.0 - charges - commissions);
-                if charges != 0 {
-                    Promise::new(self.charges_id.clone()).transfer(charges);
+                pay_account(&ft_token_id, receiver_id, amount.0 - total_fees);
+                if keeper_fee != 0 {
+                    pay_account(&ft_token_id, keeper_id.clone().unwrap(), keeper_fee);
                 }
-                if commissions!= 0 {
-                    Promise::new(self.commissions_id.clone()).transfer(commissions);
+                for (fee_account_id, fee) in fees.iter() {
+                    if *fee != 0 {
+                        self.internal_credit_fee(&nft_contract_id, &ft_token_id, fee_account_id, *fee);
+                    }
                 }
             } 
             else {
-                Promise::new(receiver_id).transfer(amount.0);
+                pay_account(&ft_token_id, receiver_id, amount.0);
             }
         }
 
+        //for fungible tokens we return 0 so that ft_resolve_transfer knows every token was used
+        if ft_token_id.as_str() != NEAR_TOKEN_ID {
+            return U128(0);
+        }
+
         //return the price payout out
         price
     }
@@ -365,7 +587,377 @@ trait ExtSelf {
     fn resolve_purchase(
         &mut self,
         buyer_id: AccountId,
+        ft_token_id: FungibleTokenId,
         price: U128,
         sale : Sale,
+        keeper_id: Option<AccountId>,
     ) -> Promise;
+
+    fn settle_auction(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        keeper_id: AccountId,
+    );
+
+    fn resolve_payment(
+        &mut self,
+        receiver_id: AccountId,
+        ft_token_id: FungibleTokenId,
+        amount: U128,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ft_callbacks::FungibleTokenReceiver;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::{json, Value};
+
+    //a sale of token 1 by alice for 1 NEAR or 1000 USDC
+    fn near_and_usdc_sale() -> Contract {
+        let mut contract = new_market();
+        call(account("owner.near"), 0, NOW);
+        contract.add_ft_token_id(usdc());
+        list(
+            &mut contract,
+            alice(),
+            "1",
+            json!({ "sale_conditions": { "near": ONE_NEAR.to_string(), "usdc.near": "1000" }, "is_auction": false }),
+            NOW,
+        );
+        contract
+    }
+
+    #[test]
+    fn a_sale_can_be_bought_in_near_at_its_near_price() {
+        let mut contract = near_and_usdc_sale();
+
+        call(bob(), ONE_NEAR, NOW);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(scheduled_call("resolve_purchase")["ft_token_id"], json!("near"));
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), ONE_NEAR)]), NOW);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), ONE_NEAR / 100 * 97)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), ONE_NEAR / 100),
+                ("near".to_string(), account("commissions.near"), ONE_NEAR / 100 * 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_same_sale_can_be_bought_in_a_token_at_its_token_price() {
+        let mut contract = near_and_usdc_sale();
+
+        call(usdc(), 0, NOW);
+        contract.ft_on_transfer(bob(), U128(1000), json!({ "nft_contract_id": nft(), "token_id": "1" }).to_string());
+        assert_eq!(scheduled_call("resolve_purchase")["ft_token_id"], json!("usdc.near"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must be greater than or equal to the current price")]
+    fn the_token_price_does_not_buy_the_sale_in_near() {
+        let mut contract = near_and_usdc_sale();
+
+        call(bob(), 1000, NOW);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn update_price_sets_the_price_in_one_currency() {
+        let mut contract = near_and_usdc_sale();
+
+        call(alice(), 1, NOW);
+        contract.update_price(nft(), "1".to_string(), Some(usdc()), U128(900));
+
+        let sale_conditions = sale(&contract, "1").unwrap().sale_conditions;
+        assert_eq!(sale_conditions.get(&usdc()), Some(&U128(900)));
+        assert_eq!(sale_conditions.get(&account("near")), Some(&U128(ONE_NEAR)));
+        assert_eq!(contract.get_sales_by_ft_token_id(usdc(), None, Some(10)).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale conditions cannot be empty")]
+    fn a_sale_needs_a_price() {
+        let mut contract = new_market();
+
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": {}, "is_auction": false }), NOW);
+    }
+
+    fn end_auction(contract: &mut Contract) {
+        call(alice(), 0, NOW + 1001);
+        contract.end_auction(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn a_missed_reserve_refunds_the_highest_bidder_and_takes_the_auction_down() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "reserve_price": (5 * ONE_NEAR).to_string() })), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        end_auction(&mut contract);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 2 * ONE_NEAR)]);
+        assert!(function_calls().iter().all(|(_, method_name, _)| method_name != "nft_transfer_payout"));
+        assert!(sale(&contract, "1").is_none());
+    }
+
+    #[test]
+    fn an_auction_kept_after_a_missed_reserve_can_be_listed_again() {
+        let mut contract = new_market();
+        let args = json!({ "reserve_price": (5 * ONE_NEAR).to_string(), "keep_if_reserve_not_met": true });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        end_auction(&mut contract);
+        assert_eq!(sale(&contract, "1").unwrap().bids.unwrap().len(), 0);
+
+        //listing it again replaces the old auction instead of keeping it
+        let mut msg = auction_msg(3 * ONE_NEAR, json!({}));
+        msg["start_time"] = json!((NOW + 2000).to_string());
+        msg["end_time"] = json!((NOW + 3000).to_string());
+        list(&mut contract, alice(), "1", msg, NOW + 1002);
+        assert_eq!(sale(&contract, "1").unwrap().auction_condition().1, 3 * ONE_NEAR);
+    }
+
+    #[test]
+    fn a_fixed_price_sale_approved_again_as_an_auction_is_replaced() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "100" }, "is_auction": false }), NOW);
+
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({})), NOW);
+        let auction = sale(&contract, "1").unwrap();
+        assert!(auction.is_auction);
+        assert_eq!(auction.auction_condition().1, ONE_NEAR);
+        assert_eq!(auction.end_time, Some(NOW + 1000));
+        assert_eq!(contract.get_expired_auctions(Some(10)).len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Auction has already ended. Relist it with nft_approve or remove it")]
+    fn an_auction_kept_after_a_missed_reserve_cannot_be_ended_again() {
+        let mut contract = new_market();
+        let args = json!({ "reserve_price": (5 * ONE_NEAR).to_string(), "keep_if_reserve_not_met": true });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        end_auction(&mut contract);
+        assert!(sale(&contract, "1").unwrap().is_ended);
+        end_auction(&mut contract);
+    }
+
+    #[test]
+    fn a_met_reserve_settles_the_auction() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "reserve_price": (2 * ONE_NEAR).to_string() })), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        end_auction(&mut contract);
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+    }
+
+    #[test]
+    fn a_hidden_reserve_is_left_out_of_the_views() {
+        let mut contract = new_market();
+        let args = json!({ "reserve_price": (5 * ONE_NEAR).to_string(), "hide_reserve_price": true });
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, args), NOW);
+        bid(&mut contract, bob(), 2 * ONE_NEAR, NOW + 1);
+
+        let info = contract.get_reserve_info(format!("{}.1", nft())).unwrap();
+        assert!(info.has_reserve && !info.reserve_met && info.reserve_price.is_none());
+        assert!(!json!(contract.get_sale(format!("{}.1", nft()))).to_string().contains(&(5 * ONE_NEAR).to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Reserve price cannot be less than the starting price")]
+    fn the_reserve_cannot_be_below_the_starting_price() {
+        let mut contract = new_market();
+
+        list(&mut contract, alice(), "1", auction_msg(ONE_NEAR, json!({ "reserve_price": "1" })), NOW);
+    }
+
+    //a dutch auction of token 1 by alice that drops from 100 yoctoNEAR at NOW to 20 at NOW + 1000
+    fn dutch_auction(contract: &mut Contract, args: Value) {
+        let mut msg = json!({
+            "sale_conditions": { "near": "100" },
+            "is_auction": false,
+            "is_dutch_auction": true,
+            "floor_price": "20",
+            "start_time": NOW.to_string(),
+            "end_time": (NOW + 1000).to_string(),
+        });
+        for (key, value) in args.as_object().unwrap() {
+            msg[key] = value.clone();
+        }
+        list(contract, alice(), "1", msg, NOW);
+    }
+
+    #[test]
+    fn dutch_auction_price_drops_linearly() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({}));
+
+        let sale = sale(&contract, "1").unwrap();
+        assert_eq!(sale.dutch_auction_price_at(NOW - 1), 100);
+        assert_eq!(sale.dutch_auction_price_at(NOW), 100);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 250), 80);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 999), 21);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 1_000), 20);
+        assert_eq!(contract.get_dutch_auction_price(format!("{}.1", nft()), Some(U64(NOW + 500))), Some(U128(60)));
+    }
+
+    #[test]
+    fn dutch_auctions_that_are_over_have_no_price_and_can_be_removed_by_anyone() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({}));
+
+        call(bob(), 0, NOW + 999);
+        assert_eq!(contract.get_dutch_auction_price(format!("{}.1", nft()), None), Some(U128(21)));
+        assert_eq!(contract.get_sales(None, Some(10)).len(), 1);
+        call(bob(), 0, NOW + 1000);
+        assert_eq!(contract.get_dutch_auction_price(format!("{}.1", nft()), None), None);
+        assert!(contract.get_sales(None, Some(10)).is_empty());
+
+        call(carol(), 1, NOW + 1000);
+        contract.remove_sale(nft(), "1".to_string());
+        assert!(sale(&contract, "1").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot buy since auction is over")]
+    fn dutch_auctions_cannot_be_bought_from_their_end_time_on() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({}));
+
+        call(bob(), 100, NOW + 1000);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn dutch_auction_price_drops_in_steps() {
+        const SECOND: u64 = 1_000_000_000;
+        let mut contract = new_market();
+        let args = json!({ "end_time": (NOW + 1000 * SECOND).to_string(), "price_step_interval": (300 * SECOND).to_string() });
+        dutch_auction(&mut contract, args);
+
+        let sale = sale(&contract, "1").unwrap();
+        assert_eq!(sale.dutch_auction_price_at(NOW + 299 * SECOND), 100);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 300 * SECOND), 76);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 899 * SECOND), 52);
+        assert_eq!(sale.dutch_auction_price_at(NOW + 900 * SECOND), 28);
+    }
+
+    #[test]
+    #[should_panic(expected = "price_step_interval must be in nanoseconds and at least 1000000000 (1 second), got 300")]
+    fn price_step_intervals_in_seconds_are_rejected() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({ "price_step_interval": "300" }));
+    }
+
+    #[test]
+    fn dutch_auction_price_does_not_overflow() {
+        //a drop of 10,000 NEAR over 30 days is far past u128::MAX when multiplied by the elapsed nanoseconds
+        const ONE_DAY: u64 = 86_400_000_000_000;
+        let mut contract = new_market();
+        let args = json!({
+            "sale_conditions": { "near": (10_000 * ONE_NEAR).to_string() },
+            "floor_price": "0",
+            "end_time": (NOW + 30 * ONE_DAY).to_string(),
+        });
+        dutch_auction(&mut contract, args);
+
+        let sale = sale(&contract, "1").unwrap();
+        assert_eq!(sale.dutch_auction_price_at(NOW + 15 * ONE_DAY), 5_000 * ONE_NEAR);
+        //the price is rounded up, so the buyer never pays less than the exact price
+        assert_eq!(sale.dutch_auction_price_at(NOW + 29 * ONE_DAY), 10_000 * ONE_NEAR / 30 + 1);
+    }
+
+    #[test]
+    fn dutch_auctions_are_bought_at_the_current_price_and_the_rest_is_refunded() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({}));
+
+        call(bob(), 100, NOW + 250);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 20)]);
+        assert_eq!(scheduled_call("nft_transfer_payout")["balance"], json!("80"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount must be greater than or equal to the current price")]
+    fn dutch_auctions_cannot_be_bought_below_the_current_price() {
+        let mut contract = new_market();
+        dutch_auction(&mut contract, json!({}));
+
+        call(bob(), 79, NOW + 250);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Floor price must be less than the starting price")]
+    fn the_floor_price_has_to_be_below_the_starting_price() {
+        let mut contract = new_market();
+
+        dutch_auction(&mut contract, json!({ "floor_price": "100" }));
+    }
+
+    //a fixed price sale of token 1 by alice for 100 NEAR that can be bought from NOW + 100 until NOW + 1000
+    fn timed_sale() -> Contract {
+        let mut contract = new_market();
+        let msg = json!({
+            "sale_conditions": { "near": "100" },
+            "is_auction": false,
+            "start_time": (NOW + 100).to_string(),
+            "end_time": (NOW + 1000).to_string(),
+        });
+        list(&mut contract, alice(), "1", msg, NOW);
+        contract
+    }
+
+    #[test]
+    fn timed_sales_can_be_bought_while_they_are_open() {
+        let mut contract = timed_sale();
+
+        call(bob(), 100, NOW + 500);
+        contract.offer(nft(), "1".to_string());
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot buy before the sale starts")]
+    fn timed_sales_cannot_be_bought_before_they_start() {
+        let mut contract = timed_sale();
+
+        call(bob(), 100, NOW + 99);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot buy since the sale is over")]
+    fn timed_sales_cannot_be_bought_from_their_end_time_on() {
+        let mut contract = timed_sale();
+
+        call(bob(), 100, NOW + 1000);
+        contract.offer(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn sales_that_are_over_are_hidden_and_can_be_removed_by_anyone() {
+        let mut contract = timed_sale();
+
+        call(bob(), 0, NOW + 999);
+        assert_eq!(contract.get_sales(None, Some(10)).len(), 1);
+        call(bob(), 0, NOW + 1000);
+        assert!(contract.get_sales(None, Some(10)).is_empty());
+        assert!(contract.get_sales_by_ft_token_id(near_token_id(), None, Some(10)).is_empty());
+
+        call(carol(), 1, NOW + 1000);
+        contract.remove_sale(nft(), "1".to_string());
+        assert!(sale(&contract, "1").is_none());
+    }
 }
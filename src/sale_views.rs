@@ -1,9 +1,18 @@
 use crate::*;
 
+//reserve information for an auction as returned by the views
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReserveInfo {
+    pub has_reserve: bool,
+    pub reserve_met: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve_price: Option<U128>, //only set if the seller didn't hide the reserve
+}
+
 #[near_bindgen]
 impl Contract {
     /// views
-    
     //returns the number of sales the marketplace has up (as a string)
     pub fn get_supply_sales(
         &self,
@@ -96,7 +105,8 @@ impl Contract {
             .take(limit.unwrap_or(0) as usize) 
             //we'll map the token IDs which are strings into Sale objects by passing in the unique sale ID (contract + DELIMITER + token ID)
             .map(|indexer| self.sales.get(&indexer).unwrap())
-            .filter(|x| !x.is_auction)
+            //fixed price sales that are over can't be bought anymore
+            .filter(|x| !x.is_auction && !x.is_expired_listing())
             //since we turned the keys into an iterator, we need to turn it back into a vector to return
             .collect()
     }
@@ -120,6 +130,28 @@ impl Contract {
             .collect()
     }
 
+    //returns paginated fixed price sales that can be bought with the given fungible token ("near" for NEAR)
+    pub fn get_sales_by_ft_token_id(
+        &self,
+        ft_token_id: FungibleTokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Sale> {
+        let keys = self.sales.keys_as_vector();
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        keys.iter()
+            .skip(start as usize) 
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize) 
+            //we'll map the token IDs which are strings into Sale objects by passing in the unique sale ID (contract + DELIMITER + token ID)
+            .map(|indexer| self.sales.get(&indexer).unwrap())
+            //only keep the sales that have a price in the given token
+            .filter(|x| !x.is_auction && !x.is_expired_listing() && x.sale_conditions.contains_key(&ft_token_id))
+            //since we turned the keys into an iterator, we need to turn it back into a vector to return
+            .collect()
+    }
+
     //returns paginated sale objects associated with a given nft contract. (result is a vector of sales)
     pub fn get_sales_by_nft_contract_id(
         &self,
@@ -162,11 +194,40 @@ impl Contract {
         self.sales.get(&nft_contract_token)
     }
 
-    pub fn get_charges_info(&self) -> (AccountId, u128) {
-        (self.charges_id.clone(), self.charges)
+    //get the time an auction currently ends at, after any anti-sniping extensions
+    pub fn get_auction_end_time(&self, nft_contract_token: ContractAndTokenId) -> Option<U64> {
+        self.sales
+            .get(&nft_contract_token)
+            .filter(|sale| sale.is_auction)
+            .and_then(|sale| sale.end_time)
+            .map(U64)
+    }
+
+    //get the price of a dutch auction at the given timestamp, or right now if no timestamp is passed in. There is no
+    //price from the end time on, since the auction can't be bought anymore
+    pub fn get_dutch_auction_price(&self, nft_contract_token: ContractAndTokenId, timestamp: Option<U64>) -> Option<U128> {
+        let timestamp = timestamp.map(|x| x.0).unwrap_or_else(env::block_timestamp);
+
+        self.sales
+            .get(&nft_contract_token)
+            .filter(|sale| sale.is_dutch_auction && sale.end_time.map(|end_time| timestamp < end_time).unwrap_or(true))
+            .map(|sale| U128(sale.dutch_auction_price_at(timestamp)))
+    }
+
+    //get whether an auction's highest bid has met its reserve. The reserve price itself is only returned if it isn't hidden
+    pub fn get_reserve_info(&self, nft_contract_token: ContractAndTokenId) -> Option<ReserveInfo> {
+        self.sales
+            .get(&nft_contract_token)
+            .filter(|sale| sale.is_auction)
+            .map(|sale| ReserveInfo {
+                has_reserve: sale.reserve_price.is_some(),
+                reserve_met: sale.is_reserve_met(),
+                reserve_price: if sale.hide_reserve_price { None } else { sale.reserve_price.map(U128) },
+            })
     }
 
-    pub fn get_commissions_info(&self) -> (AccountId, u128) {
-        (self.commissions_id.clone(), self.commissions)
+    //get the bounds (in basis points) for the minimum bid increments sellers can set, as (min, max)
+    pub fn get_bid_increment_bounds(&self) -> (U128, U128) {
+        (U128(self.min_bid_increment_bps), U128(self.max_bid_increment_bps))
     }
 }
@@ -0,0 +1,418 @@
+use crate::*;
+use crate::internal::{hash_sale_id, near_token_id, pay_account};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::log;
+
+//a committed bid on a sealed bid auction. Only the hash of the amount is known until the bid is revealed
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SealedBid {
+    pub bid_hash: Base64VecU8, //sha256 of "bidder_id:amount:salt"
+    pub deposit: U128, //amount held by the market, which has to cover the bid
+    pub revealed_amount: Option<U128>, //the bid amount once it has been revealed
+}
+
+#[near_bindgen]
+impl Contract {
+
+    //commits a sealed NEAR bid on a sealed bid auction. The attached deposit has to cover the bid and be at least the
+    //starting price. Bids in fungible tokens are committed through ft_transfer_call instead
+    #[payable]
+    pub fn commit_bid(&mut self, nft_contract_id: AccountId, token_id: String, bid_hash: Base64VecU8) {
+        let deposit = env::attached_deposit();
+        assert!(deposit > 0, "Attached deposit must be greater than 0");
+
+        self.internal_commit_bid(
+            nft_contract_id,
+            token_id,
+            near_token_id(),
+            env::predecessor_account_id(),
+            bid_hash,
+            deposit,
+        );
+    }
+
+    //reveals a sealed bid during the reveal phase (between end_time and reveal_end_time)
+    pub fn reveal_bid(&mut self, nft_contract_id: AccountId, token_id: String, amount: U128, salt: String) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
+        assert!(sale.is_sealed_bid, "Sale should be a sealed bid auction");
+
+        let current_time: u64 = env::block_timestamp();
+        assert!( sale.end_time.unwrap() <= current_time, "Cannot reveal before the bidding is over");
+        assert!( current_time < sale.reveal_end_time.unwrap(), "Cannot reveal since the reveal phase is over");
+
+        let bidder_id = env::predecessor_account_id();
+        let mut sealed_bids = self.sealed_bids.get(&contract_and_token_id).expect("No sealed bids");
+        let mut sealed_bid = sealed_bids.get(&bidder_id).expect("No sealed bid for this account");
+        assert!(sealed_bid.revealed_amount.is_none(), "Bid is already revealed");
+
+        //make sure the amount and salt are the ones that were committed to
+        let bid_hash = env::sha256(format!("{}:{}:{}", bidder_id, amount.0, salt).as_bytes());
+        assert_eq!(bid_hash, sealed_bid.bid_hash.0, "Amount and salt don't match the committed bid");
+
+        //make sure the bid is covered by the deposit and is at least the starting price
+        let (_, starting_price) = sale.auction_condition();
+        assert!(amount.0 <= sealed_bid.deposit.0, "Bid is greater than the deposit: {:?}", sealed_bid.deposit);
+        assert!(amount.0 >= starting_price, "Can't pay less than the starting price: {:?}", U128(starting_price));
+
+        sealed_bid.revealed_amount = Some(amount);
+        sealed_bids.insert(&bidder_id, &sealed_bid);
+        self.sealed_bids.insert(&contract_and_token_id, &sealed_bids);
+    }
+
+    /// views
+    //returns the sealed bids on an auction. Amounts are only known for bids that have been revealed
+    pub fn get_sealed_bids(&self, nft_contract_token: ContractAndTokenId) -> Vec<(AccountId, SealedBid)> {
+        self.sealed_bids
+            .get(&nft_contract_token)
+            .map(|sealed_bids| sealed_bids.to_vec())
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    //internal method for committing a sealed bid. The deposit is in the token the auction is priced in and is already held by the market.
+    //The bidder pays for the storage of the commitment out of their storage deposit, like for any other bid
+    pub(crate) fn internal_commit_bid(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        ft_token_id: FungibleTokenId,
+        bidder_id: AccountId,
+        bid_hash: Base64VecU8,
+        deposit: u128,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("Doesn't exist");
+        assert!(sale.is_sealed_bid, "Sale should be a sealed bid auction");
+        assert_ne!(sale.owner_id, bidder_id, "Cannot bid on your own sale.");
+
+        let current_time: u64 = env::block_timestamp();
+        assert!( sale.start_time.unwrap() < current_time, "Cannot bid before auction starts");
+        assert!( current_time < sale.end_time.unwrap() ,"Cannot bid since auction is over" );
+
+        //make sure the deposit is in the token the auction is priced in and covers at least the starting price,
+        //which is the lowest bid that can be revealed
+        let (auction_ft_token_id, starting_price) = sale.auction_condition();
+        assert_eq!(
+            auction_ft_token_id,
+            ft_token_id,
+            "This auction is priced in {}",
+            auction_ft_token_id
+        );
+        assert!(deposit >= starting_price, "Deposit must be at least the starting price: {:?}", U128(starting_price));
+
+        //get the sealed bids for the auction. If there are none, we create a new empty map
+        let mut sealed_bids = self.sealed_bids.get(&contract_and_token_id).unwrap_or_else(|| {
+            UnorderedMap::new(
+                StorageKey::SealedBidsInner {
+                    //we get a new unique prefix for the collection by hashing the sale ID
+                    sale_id_hash: hash_sale_id(&contract_and_token_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+
+        assert!(sealed_bids.get(&bidder_id).is_none(), "Already committed a bid on this auction");
+
+        sealed_bids.insert(
+            &bidder_id,
+            &SealedBid {
+                bid_hash,
+                deposit: U128(deposit),
+                revealed_amount: None,
+            },
+        );
+        self.sealed_bids.insert(&contract_and_token_id, &sealed_bids);
+        self.internal_charge_storage(&bidder_id, initial_storage_usage);
+    }
+
+    //whether anyone has committed a bid on the auction
+    pub(crate) fn has_sealed_bids(&self, contract_and_token_id: &ContractAndTokenId) -> bool {
+        self.sealed_bids.get(contract_and_token_id).is_some()
+    }
+
+    /*
+        internal method for settling a sealed bid auction once the reveal phase is over. The highest revealed bid
+        wins and pays its own bid (or the second price). Everyone gets their deposit back except for the price paid, and bidders that
+        never revealed lose the forfeit penalty, which goes to the seller. Deposits are credited to the bidders' pending balances
+        for them to withdraw instead of being sent, so settling doesn't need a payment for every bid.
+    */
+    pub(crate) fn internal_settle_sealed_bid_auction(&mut self, sale: Sale, keeper_id: Option<AccountId>) {
+        let nft_contract_id: AccountId = sale.nft_contract_id.parse().unwrap();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, sale.token_id);
+        let (ft_token_id, _) = sale.auction_condition();
+
+        let mut sealed_bids = match self.sealed_bids.remove(&contract_and_token_id) {
+            Some(sealed_bids) => sealed_bids,
+            //nobody bid, so the auction is relisted or removed
+            None => {
+                self.internal_relist_or_remove(sale);
+                return;
+            }
+        };
+
+        //find the highest revealed bid. Ties go to whoever committed first
+        let mut winner: Option<(AccountId, u128)> = None;
+        for (bidder_id, sealed_bid) in sealed_bids.iter() {
+            if let Some(amount) = sealed_bid.revealed_amount {
+                if winner.as_ref().map(|(_, winning_amount)| amount.0 > *winning_amount).unwrap_or(true) {
+                    winner = Some((bidder_id, amount.0));
+                }
+            }
+        }
+
+        //a winning bid below the reserve doesn't win
+        if let (Some((_, winning_amount)), Some(reserve_price)) = (winner.as_ref(), sale.reserve_price) {
+            if *winning_amount < reserve_price {
+                log!("Reserve price not met for {}", contract_and_token_id);
+                winner = None;
+            }
+        }
+
+        //the winner pays their own bid, or one increment above the highest revealed bid of anyone else for second price auctions
+        let winner = winner.map(|(winner_id, winning_amount)| {
+            let runner_up_amount = sealed_bids
+                .iter()
+                .filter(|(bidder_id, _)| *bidder_id != winner_id)
+                .filter_map(|(_, sealed_bid)| sealed_bid.revealed_amount.map(|amount| amount.0))
+                .max();
+            let price = sale.winning_price(winning_amount, runner_up_amount);
+            (winner_id, price)
+        });
+
+        //refund every deposit, minus the price paid for the winner and the penalty for bids that weren't revealed
+        let mut forfeited: u128 = 0;
+        for (bidder_id, sealed_bid) in sealed_bids.iter() {
+            let refund = match (&winner, sealed_bid.revealed_amount) {
+                (Some((winner_id, winning_amount)), Some(_)) if *winner_id == bidder_id => sealed_bid.deposit.0 - winning_amount,
+                (_, Some(_)) => sealed_bid.deposit.0,
+                (_, None) => {
+                    let penalty = sale.forfeit_penalty.unwrap_or(0).min(sealed_bid.deposit.0);
+                    forfeited += penalty;
+                    sealed_bid.deposit.0 - penalty
+                }
+            };
+
+            if refund > 0 {
+                self.internal_credit_pending(&bidder_id, &ft_token_id, refund);
+            }
+        }
+        sealed_bids.clear();
+
+        if forfeited > 0 {
+            pay_account(&ft_token_id, sale.owner_id.clone(), forfeited);
+        }
+
+        match winner {
+            Some((buyer_id, winning_amount)) => {
+                self.process_purchase(
+                    nft_contract_id,
+                    sale.token_id,
+                    ft_token_id,
+                    U128(winning_amount),
+                    buyer_id,
+                    keeper_id,
+                );
+            }
+            None if sale.keep_if_reserve_not_met => {
+                //leave the auction on the market without bids so the seller can relist it with nft_approve.
+                //it no longer needs to be settled so it comes out of the end time index
+                let mut sale = sale;
+                self.internal_unindex_auction(&contract_and_token_id, &sale);
+                sale.is_ended = true;
+                self.sales.insert(&contract_and_token_id, &sale);
+            }
+            None => {
+                self.internal_remove_sale(nft_contract_id, sale.token_id);
+            }
+        }
+    }
+
+    //internal method for refunding every sealed bid in full when an auction is taken down. The deposits are credited
+    //to the bidders' pending balances
+    pub(crate) fn internal_refund_sealed_bids(&mut self, contract_and_token_id: &ContractAndTokenId, ft_token_id: &FungibleTokenId) {
+        if let Some(mut sealed_bids) = self.sealed_bids.remove(contract_and_token_id) {
+            for (bidder_id, sealed_bid) in sealed_bids.iter() {
+                self.internal_credit_pending(&bidder_id, ft_token_id, sealed_bid.deposit.0);
+            }
+            sealed_bids.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::json;
+
+    //a sealed bid auction of token 1 by alice starting at 100, with bids revealed until NOW + 2000 and a penalty of 10
+    fn sealed_auction() -> Contract {
+        let mut contract = new_market();
+        let msg = auction_msg(100, json!({
+            "is_sealed_bid": true,
+            "reveal_end_time": (NOW + 2000).to_string(),
+            "forfeit_penalty": "10",
+        }));
+        list(&mut contract, alice(), "1", msg, NOW);
+        contract
+    }
+
+    fn bid_hash(bidder_id: &AccountId, amount: u128, salt: &str) -> Base64VecU8 {
+        Base64VecU8(env::sha256(format!("{}:{}:{}", bidder_id, amount, salt).as_bytes()))
+    }
+
+    fn commit(contract: &mut Contract, bidder_id: AccountId, deposit: u128, amount: u128) {
+        deposit_storage(contract, bidder_id.clone(), NOW + 1);
+        call(bidder_id.clone(), deposit, NOW + 1);
+        contract.commit_bid(nft(), "1".to_string(), bid_hash(&bidder_id, amount, "salt"));
+    }
+
+    fn reveal(contract: &mut Contract, bidder_id: AccountId, amount: u128) {
+        call(bidder_id, 0, NOW + 1500);
+        contract.reveal_bid(nft(), "1".to_string(), U128(amount), "salt".to_string());
+    }
+
+    //the deposits credited back to the bidders, as (bidder, amount)
+    fn returned_deposits(contract: &Contract) -> Vec<(AccountId, u128)> {
+        let mut returned = vec![];
+        for bidder_id in [bob(), carol()] {
+            for (_, amount) in contract.get_pending_balances(bidder_id.clone()) {
+                returned.push((bidder_id.clone(), amount.0));
+            }
+        }
+        returned
+    }
+
+    fn settle(contract: &mut Contract) {
+        call(alice(), 0, NOW + 2001);
+        contract.end_auction(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn highest_revealed_bid_wins_and_pays_its_own_bid() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 300, 200);
+        commit(&mut contract, carol(), 250, 250);
+        reveal(&mut contract, bob(), 200);
+        reveal(&mut contract, carol(), 250);
+
+        settle(&mut contract);
+        assert!(payments().is_empty());
+        assert_eq!(returned_deposits(&contract), vec![(bob(), 300)]);
+        let args = scheduled_call("resolve_purchase");
+        assert_eq!(args["buyer_id"], json!(carol()));
+        assert_eq!(args["price"], json!("250"));
+    }
+
+    #[test]
+    fn winner_gets_back_what_the_deposit_covers_above_the_bid() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 300, 200);
+        reveal(&mut contract, bob(), 200);
+
+        settle(&mut contract);
+        assert_eq!(returned_deposits(&contract), vec![(bob(), 100)]);
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("200"));
+    }
+
+    #[test]
+    fn bids_that_are_never_revealed_forfeit_the_penalty_to_the_seller() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 200, 200);
+        commit(&mut contract, carol(), 250, 250);
+        reveal(&mut contract, bob(), 200);
+
+        settle(&mut contract);
+        assert_eq!(payments(), vec![("near".to_string(), alice(), 10)]);
+        assert_eq!(returned_deposits(&contract), vec![(carol(), 240)]);
+        assert_eq!(scheduled_call("resolve_purchase")["buyer_id"], json!(bob()));
+        assert!(contract.get_sealed_bids(format!("{}{}{}", nft(), DELIMETER, "1")).is_empty());
+    }
+
+    #[test]
+    fn removing_the_auction_refunds_every_sealed_bid_in_full() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 200, 200);
+
+        call(alice(), 1, NOW + 2);
+        contract.remove_sale(nft(), "1".to_string());
+        assert_eq!(returned_deposits(&contract), vec![(bob(), 200)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Please use storage_deposit")]
+    fn bidders_pay_for_the_storage_of_their_commitment() {
+        let mut contract = sealed_auction();
+
+        call(bob(), 300, NOW + 1);
+        contract.commit_bid(nft(), "1".to_string(), bid_hash(&bob(), 200, "salt"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount and salt don't match the committed bid")]
+    fn reveal_has_to_match_the_commitment() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 300, 200);
+        reveal(&mut contract, bob(), 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid is greater than the deposit")]
+    fn revealed_bid_has_to_be_covered_by_the_deposit() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 150, 200);
+        reveal(&mut contract, bob(), 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit must be at least the starting price: U128(100)")]
+    fn deposits_have_to_cover_the_starting_price() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 99, 99);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot reveal before the bidding is over")]
+    fn bids_cannot_be_revealed_before_the_end_time() {
+        let mut contract = sealed_auction();
+        commit(&mut contract, bob(), 300, 200);
+
+        call(bob(), 0, NOW + 2);
+        contract.reveal_bid(nft(), "1".to_string(), U128(200), "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Please use commit_bid function to bid on this sealed bid auction!")]
+    fn open_bids_on_a_sealed_bid_auction_are_rejected() {
+        let mut contract = sealed_auction();
+
+        call(bob(), 200, NOW + 1);
+        contract.add_bid(nft(), "1".to_string());
+    }
+
+    #[test]
+    fn second_price_winners_pay_one_increment_above_the_runner_up() {
+        let mut contract = new_market();
+        let msg = auction_msg(100, json!({
+            "is_sealed_bid": true,
+            "reveal_end_time": (NOW + 2000).to_string(),
+            "is_second_price": true,
+            "min_bid_increment": "10",
+        }));
+        list(&mut contract, alice(), "1", msg, NOW);
+        commit(&mut contract, bob(), 300, 200);
+        commit(&mut contract, carol(), 250, 250);
+        reveal(&mut contract, bob(), 200);
+        reveal(&mut contract, carol(), 250);
+
+        settle(&mut contract);
+        assert_eq!(returned_deposits(&contract), vec![(bob(), 300), (carol(), 40)]);
+        assert_eq!(scheduled_call("resolve_purchase")["price"], json!("210"));
+    }
+}
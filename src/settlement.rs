@@ -0,0 +1,273 @@
+use crate::*;
+
+//GAS attached to settle an auction that sells. This covers ending the auction, a refund, the nft transfer and paying out royalties
+const GAS_FOR_SETTLE_SALE: Gas = Gas(185_000_000_000_000);
+//GAS attached to settle an auction that doesn't sell. This covers a refund and relisting or removing the auction
+const GAS_FOR_SETTLE_NO_SALE: Gas = Gas(20_000_000_000_000);
+//GAS added for every bid on a sealed bid auction, whose deposit is credited back to the bidder when it's settled
+const GAS_PER_SEALED_BID: Gas = Gas(2_000_000_000_000);
+//GAS kept back for the rest of the settle_expired_auctions call
+const GAS_FOR_SETTLE_BATCH: Gas = Gas(10_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+
+    /*
+        settles up to `limit` auctions that are past their end time, oldest first. Anyone can call this and the caller
+        is paid the keeper fee out of the marketplace's fees for every auction that sells. Each auction is settled in
+        its own call with the GAS it needs, so one auction failing doesn't stop the others. Auctions that don't fit in
+        the GAS that is left are skipped and left for a later call. This returns how many were started.
+    */
+    pub fn settle_expired_auctions(&mut self, limit: u64) -> u64 {
+        let keeper_id = env::predecessor_account_id();
+        let current_time: u64 = env::block_timestamp();
+
+        //get the auctions that are over, oldest first
+        let expired: Vec<(u64, ContractAndTokenId)> = self
+            .auctions_by_end_time
+            .iter()
+            .take_while(|((end_time, _), _)| *end_time < current_time)
+            .take(limit as usize)
+            .map(|(key, _)| key)
+            .collect();
+
+        let mut settled = 0;
+        for (end_time, contract_and_token_id) in expired {
+            //drop index entries for sales that are no longer on the market
+            let sale = match self.sales.get(&contract_and_token_id) {
+                Some(sale) => sale,
+                None => {
+                    self.auctions_by_end_time.remove(&(end_time, contract_and_token_id));
+                    continue;
+                }
+            };
+
+            //skip auctions there isn't enough GAS left to settle
+            let settle_gas = self.internal_settlement_gas(&contract_and_token_id, &sale);
+            if env::prepaid_gas() - env::used_gas() < settle_gas + GAS_FOR_SETTLE_BATCH {
+                continue;
+            }
+
+            //the auction comes out of the index as soon as it's scheduled, so an auction that fails to settle can't hold up
+            //the ones after it. It can still be ended with end_auction
+            self.auctions_by_end_time.remove(&(end_time, contract_and_token_id));
+            ext_self::settle_auction(
+                sale.nft_contract_id.parse().unwrap(),
+                sale.token_id,
+                keeper_id.clone(),
+                env::current_account_id(), //we are invoking this function on the current contract
+                NO_DEPOSIT, //don't attach any deposit
+                settle_gas, //GAS attached to the call to settle the auction
+            );
+            settled += 1;
+        }
+
+        settled
+    }
+
+    //private method used by settle_expired_auctions to end a single auction on behalf of a keeper
+    #[private]
+    pub fn settle_auction(&mut self, nft_contract_id: AccountId, token_id: String, keeper_id: AccountId) {
+        self.internal_end_auction(nft_contract_id, token_id, Some(keeper_id));
+    }
+
+    /// views
+    //returns up to `limit` auctions that are over and waiting to be settled, oldest first
+    pub fn get_expired_auctions(&self, limit: Option<u64>) -> Vec<ContractAndTokenId> {
+        let current_time: u64 = env::block_timestamp();
+
+        self.auctions_by_end_time
+            .iter()
+            .take_while(|((end_time, _), _)| *end_time < current_time)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .map(|((_, contract_and_token_id), _)| contract_and_token_id)
+            .collect()
+    }
+}
+
+impl Contract {
+    //internal method for the GAS it takes to settle an auction. Auctions with a winning bid need enough for the purchase,
+    //and sealed bid auctions (which could sell to any revealed bid) also need some for every bid
+    pub(crate) fn internal_settlement_gas(&self, contract_and_token_id: &ContractAndTokenId, sale: &Sale) -> Gas {
+        if sale.is_sealed_bid {
+            return match self.sealed_bids.get(contract_and_token_id) {
+                Some(sealed_bids) => GAS_FOR_SETTLE_SALE + GAS_PER_SEALED_BID * sealed_bids.len(),
+                None => GAS_FOR_SETTLE_NO_SALE,
+            };
+        }
+
+        let has_bids = sale.bids.as_ref().map(|bids| !bids.is_empty()).unwrap_or(false);
+        if has_bids && sale.is_reserve_met() { GAS_FOR_SETTLE_SALE } else { GAS_FOR_SETTLE_NO_SALE }
+    }
+
+    //internal method for adding an auction to the end time index
+    pub(crate) fn internal_index_auction(&mut self, contract_and_token_id: &ContractAndTokenId, sale: &Sale) {
+        if let Some(settlement_time) = sale.settlement_time() {
+            self.auctions_by_end_time.insert(&(settlement_time, contract_and_token_id.clone()), &());
+        }
+    }
+
+    //internal method for removing an auction from the end time index
+    pub(crate) fn internal_unindex_auction(&mut self, contract_and_token_id: &ContractAndTokenId, sale: &Sale) {
+        if let Some(settlement_time) = sale.settlement_time() {
+            self.auctions_by_end_time.remove(&(settlement_time, contract_and_token_id.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::{json, Value};
+    use near_sdk::PromiseResult;
+
+    //two auctions by alice: token 1 ends at NOW + 1000 and token 2 at NOW + 500
+    fn auctions() -> Contract {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", auction_msg(1000, json!({ "min_bid_increment": "100" })), NOW);
+        list(&mut contract, alice(), "2", auction_msg(1000, json!({ "end_time": (NOW + 500).to_string() })), NOW);
+        contract
+    }
+
+    fn sale_id(token_id: &str) -> ContractAndTokenId {
+        format!("{}{}{}", nft(), DELIMETER, token_id)
+    }
+
+    #[test]
+    fn expired_auctions_are_listed_oldest_first() {
+        let contract = auctions();
+
+        call(carol(), 0, NOW + 600);
+        assert_eq!(contract.get_expired_auctions(Some(10)), vec![sale_id("2")]);
+        call(carol(), 0, NOW + 1001);
+        assert_eq!(contract.get_expired_auctions(Some(10)), vec![sale_id("2"), sale_id("1")]);
+        assert_eq!(contract.get_expired_auctions(Some(1)), vec![sale_id("2")]);
+    }
+
+    fn settle_auction_calls() -> Vec<Value> {
+        function_calls()
+            .into_iter()
+            .filter(|(_, method_name, _)| method_name == "settle_auction")
+            .map(|(_, _, args)| args)
+            .collect()
+    }
+
+    #[test]
+    fn the_crank_settles_each_auction_in_its_own_call_and_unindexes_it() {
+        let mut contract = auctions();
+
+        call(carol(), 0, NOW + 1001);
+        assert_eq!(contract.settle_expired_auctions(10), 2);
+        let calls = settle_auction_calls();
+        assert_eq!((calls[0]["token_id"].clone(), calls[1]["token_id"].clone()), (json!("2"), json!("1")));
+        assert_eq!(calls[0]["keeper_id"], json!(carol()));
+        assert!(contract.get_expired_auctions(Some(10)).is_empty());
+    }
+
+    #[test]
+    fn auctions_that_sell_get_the_gas_for_the_purchase() {
+        let mut contract = auctions();
+        bid(&mut contract, bob(), 10_000, NOW + 1);
+
+        //the 300 TGas of a transaction covers one sale, and the auction without bids is settled with what is left
+        call(carol(), 0, NOW + 1001);
+        assert_eq!(contract.settle_expired_auctions(10), 2);
+        let calls = settle_auction_calls();
+        assert_eq!((calls[0]["token_id"].clone(), calls[1]["token_id"].clone()), (json!("2"), json!("1")));
+
+    }
+
+    #[test]
+    fn sales_that_do_not_fit_in_the_gas_are_left_for_the_next_call() {
+        let mut contract = auctions();
+        bid(&mut contract, bob(), 10_000, NOW + 1);
+        deposit_storage(&mut contract, bob(), NOW + 1);
+        call(bob(), ONE_NEAR, NOW + 1);
+        contract.add_bid(nft(), "2".to_string());
+
+        call(carol(), 0, NOW + 1001);
+        assert_eq!(contract.settle_expired_auctions(10), 1);
+        assert_eq!(settle_auction_calls()[0]["token_id"], "2");
+        assert_eq!(contract.get_expired_auctions(Some(10)), vec![sale_id("1")]);
+    }
+
+    #[test]
+    fn keepers_are_paid_out_of_the_charges() {
+        let mut contract = auctions();
+        call(account("owner.near"), 0, NOW);
+        contract.change_keeper_fee(50);
+        bid(&mut contract, bob(), 10_000, NOW + 1);
+
+        callback(PromiseResult::Successful(vec![]), NOW + 1001);
+        contract.settle_auction(nft(), "1".to_string(), carol());
+        assert_eq!(scheduled_call("resolve_purchase")["keeper_id"], json!(carol()));
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), 10_000)]), NOW + 1001);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(
+            paid,
+            vec![
+                ("near".to_string(), alice(), 9_700),
+                ("near".to_string(), carol(), 50),
+            ]
+        );
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 50),
+                ("near".to_string(), account("commissions.near"), 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_keeper_fee_is_taken_from_the_fee_entries_in_order() {
+        let mut contract = auctions();
+        call(account("owner.near"), 0, NOW);
+        contract.change_keeper_fee(50);
+        contract.set_fee_table(vec![
+            FeeEntry { account_id: account("charges.near"), bps: 30 },
+            FeeEntry { account_id: account("commissions.near"), bps: 200 },
+        ]);
+        bid(&mut contract, bob(), 10_000, NOW + 1);
+
+        callback(PromiseResult::Successful(vec![]), NOW + 1001);
+        contract.settle_auction(nft(), "1".to_string(), carol());
+        resolve_purchase(&mut contract, Some(vec![(alice(), 10_000)]), NOW + 1001);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(
+            paid,
+            vec![
+                ("near".to_string(), alice(), 9_770),
+                ("near".to_string(), carol(), 50),
+            ]
+        );
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![("near".to_string(), account("commissions.near"), 180)]
+        );
+    }
+
+    #[test]
+    fn auctions_without_bids_are_taken_down_and_unindexed() {
+        let mut contract = auctions();
+
+        callback(PromiseResult::Successful(vec![]), NOW + 600);
+        contract.settle_auction(nft(), "2".to_string(), carol());
+        assert!(sale(&contract, "2").is_none());
+        assert!(contract.get_expired_auctions(Some(10)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot end before end_time mentioned for the auction")]
+    fn auctions_cannot_be_settled_before_they_end() {
+        let mut contract = auctions();
+
+        callback(PromiseResult::Successful(vec![]), NOW + 600);
+        contract.settle_auction(nft(), "1".to_string(), carol());
+    }
+}
@@ -0,0 +1,191 @@
+//helpers shared by the unit tests. They run the market like the runtime would: calls come in through the public
+//methods and the promises they create are read back from the mocked blockchain
+use crate::*;
+use crate::nft_callbacks::NonFungibleTokenApprovalsReceiver;
+use near_sdk::mock::VmAction;
+use near_sdk::serde_json::{self, json, Value};
+use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+use near_sdk::{testing_env, PromiseResult};
+
+//a block time in nanoseconds that the tests start at
+pub(crate) const NOW: u64 = 1_700_000_000_000_000_000;
+pub(crate) const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+pub(crate) fn account(account_id: &str) -> AccountId {
+    account_id.parse().unwrap()
+}
+
+pub(crate) fn market() -> AccountId {
+    account("market.near")
+}
+
+pub(crate) fn nft() -> AccountId {
+    account("nft.near")
+}
+
+pub(crate) fn usdc() -> AccountId {
+    account("usdc.near")
+}
+
+pub(crate) fn alice() -> AccountId {
+    account("alice.near")
+}
+
+pub(crate) fn bob() -> AccountId {
+    account("bob.near")
+}
+
+pub(crate) fn carol() -> AccountId {
+    account("carol.near")
+}
+
+fn context(predecessor_id: AccountId, signer_id: AccountId, deposit: Balance, timestamp: u64) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(market())
+        .predecessor_account_id(predecessor_id)
+        .signer_account_id(signer_id)
+        .attached_deposit(deposit)
+        .block_timestamp(timestamp);
+    builder
+}
+
+//sets up a call from the given account with the given deposit at the given time
+pub(crate) fn call(account_id: AccountId, deposit: Balance, timestamp: u64) {
+    testing_env!(context(account_id.clone(), account_id, deposit, timestamp).build());
+}
+
+//sets up a callback to the market that gets the given result from the promise it waited for
+pub(crate) fn callback(result: PromiseResult, timestamp: u64) {
+    testing_env!(
+        context(market(), market(), 0, timestamp).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![result],
+    );
+}
+
+//a market owned by owner.near that takes 1% in charges and 2% in commissions
+pub(crate) fn new_market() -> Contract {
+    call(account("owner.near"), 0, NOW);
+    Contract::new(account("owner.near"), account("charges.near"), 100, account("commissions.near"), 200)
+}
+
+//pays 1 NEAR for the account's storage, which covers its sales, offers and bid history in the tests
+pub(crate) fn deposit_storage(contract: &mut Contract, account_id: AccountId, timestamp: u64) {
+    call(account_id, ONE_NEAR, timestamp);
+    contract.storage_deposit(None);
+}
+
+//lists the token for its owner like nft_approve on the NFT contract does, after paying for the storage
+pub(crate) fn list(contract: &mut Contract, owner_id: AccountId, token_id: &str, msg: Value, timestamp: u64) {
+    deposit_storage(contract, owner_id.clone(), timestamp);
+    approve(contract, owner_id, token_id, msg, timestamp);
+}
+
+//bids on token 1 in NEAR after paying for the bidder's storage
+pub(crate) fn bid(contract: &mut Contract, bidder_id: AccountId, amount: u128, timestamp: u64) {
+    deposit_storage(contract, bidder_id.clone(), timestamp);
+    call(bidder_id, amount, timestamp);
+    contract.add_bid(nft(), "1".to_string());
+}
+
+//approves the market on the token for its owner like nft_approve on the NFT contract does
+pub(crate) fn approve(contract: &mut Contract, owner_id: AccountId, token_id: &str, msg: Value, timestamp: u64) {
+    testing_env!(context(nft(), owner_id.clone(), 0, timestamp).build());
+    contract.nft_on_approve(token_id.to_string(), owner_id, 1, msg.to_string());
+}
+
+//the nft_approve msg for an auction in NEAR starting at the given price that runs from NOW until NOW + 1000, with the
+//given args on top
+pub(crate) fn auction_msg(starting_price: u128, args: Value) -> Value {
+    let mut msg = json!({
+        "sale_conditions": { "near": starting_price.to_string() },
+        "is_auction": true,
+        "start_time": NOW.to_string(),
+        "end_time": (NOW + 1000).to_string(),
+    });
+    for (key, value) in args.as_object().unwrap() {
+        msg[key] = value.clone();
+    }
+    msg
+}
+
+pub(crate) fn sale(contract: &Contract, token_id: &str) -> Option<Sale> {
+    contract.sales.get(&format!("{}{}{}", nft(), DELIMETER, token_id))
+}
+
+//the function calls the last call created, as (contract, method, args)
+pub(crate) fn function_calls() -> Vec<(AccountId, String, Value)> {
+    let mut calls = vec![];
+    for receipt in get_created_receipts() {
+        for action in receipt.actions {
+            if let VmAction::FunctionCall { method_name, args, .. } = action {
+                calls.push((receipt.receiver_id.clone(), method_name, serde_json::from_slice(&args).unwrap()));
+            }
+        }
+    }
+    calls
+}
+
+//the payments the last call created, as (token, receiver, amount). NEAR transfers are in "near"
+pub(crate) fn payments() -> Vec<(String, AccountId, u128)> {
+    let mut payments = vec![];
+    for receipt in get_created_receipts() {
+        for action in receipt.actions {
+            if let VmAction::Transfer { deposit } = action {
+                payments.push((NEAR_TOKEN_ID.to_string(), receipt.receiver_id.clone(), deposit));
+            }
+        }
+    }
+    for (ft_token_id, method_name, args) in function_calls() {
+        if method_name == "ft_transfer" {
+            let amount: U128 = serde_json::from_value(args["amount"].clone()).unwrap();
+            payments.push((ft_token_id.to_string(), serde_json::from_value(args["receiver_id"].clone()).unwrap(), amount.0));
+        }
+    }
+    payments
+}
+
+//the arguments of the last call to the given method that the last call created
+pub(crate) fn scheduled_call(method_name: &str) -> Value {
+    function_calls()
+        .into_iter()
+        .rev()
+        .find(|(_, method, _)| method == method_name)
+        .map(|(_, _, args)| args)
+        .unwrap_or_else(|| panic!("{} was not called", method_name))
+}
+
+//runs the resolve_purchase callback the last call scheduled. The NFT contract returns the given payout, or fails the
+//transfer if there is none
+pub(crate) fn resolve_purchase(contract: &mut Contract, payout: Option<Vec<(AccountId, u128)>>, timestamp: u64) -> U128 {
+    let args = scheduled_call("resolve_purchase");
+    let result = match payout {
+        Some(payout) => {
+            let payout: HashMap<AccountId, U128> = payout.into_iter().map(|(account_id, amount)| (account_id, U128(amount))).collect();
+            PromiseResult::Successful(json!({ "payout": payout }).to_string().into_bytes())
+        }
+        None => PromiseResult::Failed,
+    };
+    callback(result, timestamp);
+    contract.resolve_purchase(
+        serde_json::from_value(args["buyer_id"].clone()).unwrap(),
+        serde_json::from_value(args["ft_token_id"].clone()).unwrap(),
+        serde_json::from_value(args["price"].clone()).unwrap(),
+        serde_json::from_value(args["sale"].clone()).unwrap(),
+        serde_json::from_value(args["keeper_id"].clone()).unwrap(),
+    )
+}
+
+//the fees credited to the given accounts that they can withdraw, as (token, account, amount)
+pub(crate) fn fee_balances(contract: &Contract, account_ids: &[AccountId]) -> Vec<(String, AccountId, u128)> {
+    let mut balances = vec![];
+    for account_id in account_ids {
+        for (ft_token_id, amount) in contract.get_fee_balances(account_id.clone()) {
+            balances.push((ft_token_id.to_string(), account_id.clone(), amount.0));
+        }
+    }
+    balances
+}
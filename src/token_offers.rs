@@ -0,0 +1,374 @@
+use crate::*;
+use crate::internal::{hash_account_id, hash_sale_id, near_token_id, pay_account};
+use crate::offer_books::{MAX_OFFERS_SCANNED, assert_valid_expiry, insert_into_account_set, insert_into_book, is_expired, remove_from_account_set, remove_from_book};
+
+//struct for an offer to buy a specific token, whether it is listed or not. The price is escrowed by the market until
+//the offer is accepted, cancelled or removed after it expires
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenOffer {
+    pub offer_id: u64,
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub price: U128, //in yoctoNEAR
+    pub expires_at: Option<U64>, //Unix timestamp (in nanoseconds) the offer can't be accepted after
+}
+
+impl TokenOffer {
+    //whether the offer is past its expiry
+    pub(crate) fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //offers to buy the given token for the attached deposit, which the market holds until the offer is accepted
+    //or cancelled. Like a sale, every open offer takes up one sale worth of storage deposit
+    #[payable]
+    pub fn make_token_offer(&mut self, nft_contract_id: AccountId, token_id: TokenId, expires_at: Option<U64>) -> u64 {
+        let price = env::attached_deposit();
+        assert!(price > 0, "Attached deposit must be greater than 0");
+
+        assert_valid_expiry(expires_at);
+
+        //if the token is listed, we know its owner and make sure they're not the buyer
+        let buyer_id = env::predecessor_account_id();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            assert_ne!(sale.owner_id, buyer_id, "Cannot make an offer on your own token");
+        }
+
+        //make sure the buyer has paid for the storage of one more offer
+        self.internal_assert_offer_storage(&buyer_id);
+
+        let offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+
+        let offer = TokenOffer {
+            offer_id,
+            buyer_id: buyer_id.clone(),
+            nft_contract_id,
+            token_id,
+            price: U128(price),
+            expires_at,
+        };
+        self.token_offers.insert(&offer_id, &offer);
+
+        //add the offer to the token's offer book and to the buyer's offers. We get new unique prefixes by hashing the sale ID and the buyer
+        insert_into_book(
+            &mut self.token_offer_book,
+            &contract_and_token_id,
+            StorageKey::TokenOfferBookInner { sale_id_hash: hash_sale_id(&contract_and_token_id) },
+            price,
+            offer_id,
+        );
+        insert_into_account_set(
+            &mut self.token_offers_by_buyer_id,
+            &buyer_id,
+            StorageKey::TokenOffersByBuyerIdInner { account_id_hash: hash_account_id(&buyer_id) },
+            offer_id,
+        );
+
+        offer_id
+    }
+
+    //cancels a token offer and refunds it. The buyer can cancel at any time and anyone can remove an expired offer
+    #[payable]
+    pub fn cancel_token_offer(&mut self, offer_id: u64) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+        let offer = self.token_offers.get(&offer_id).expect("No token offer");
+
+        if env::predecessor_account_id() != offer.buyer_id {
+            assert!(offer.is_expired(), "Only the buyer can cancel an offer before it expires");
+        }
+
+        let offer = self.internal_remove_token_offer(offer_id);
+        pay_account(&near_token_id(), offer.buyer_id, offer.price.0);
+    }
+
+    /// views
+    //returns paginated offers for a given unique sale ID (contract + DELIMITER + token ID), highest price first.
+    //Ties go to the oldest offer
+    pub fn get_token_offers(
+        &self,
+        nft_contract_token: ContractAndTokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenOffer> {
+        let offer_book = match self.token_offer_book.get(&nft_contract_token) {
+            Some(offer_book) => offer_book,
+            None => return vec![],
+        };
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        offer_book
+            .iter_rev()
+            .skip(start as usize)
+            //take the first "limit" elements. If we didn't specify a limit, use 0
+            .take(limit.unwrap_or(0) as usize)
+            .map(|(_, offer_id)| self.token_offers.get(&offer_id).unwrap())
+            .collect()
+    }
+
+    //returns every token offer made by the given buyer
+    pub fn get_token_offers_by_buyer_id(&self, buyer_id: AccountId) -> Vec<TokenOffer> {
+        self.token_offers_by_buyer_id
+            .get(&buyer_id)
+            .map(|by_buyer_id| {
+                by_buyer_id
+                    .iter()
+                    .map(|offer_id| self.token_offers.get(&offer_id).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    //internal method for removing a token offer from the market. The escrowed price is not refunded here
+    pub(crate) fn internal_remove_token_offer(&mut self, offer_id: u64) -> TokenOffer {
+        let offer = self.token_offers.remove(&offer_id).expect("No token offer");
+        let contract_and_token_id = format!("{}{}{}", offer.nft_contract_id, DELIMETER, offer.token_id);
+
+        remove_from_book(&mut self.token_offer_book, &contract_and_token_id, offer.price.0, offer_id);
+        remove_from_account_set(&mut self.token_offers_by_buyer_id, &offer.buyer_id, offer_id);
+
+        //the seller's counter offer goes with it
+        self.internal_remove_counter_offer(offer_id);
+
+        offer
+    }
+
+    //internal method for finding the best offer the given owner can accept for the given token. Their own offers are
+    //skipped. Expired offers found along the way are removed and refunded and only the first MAX_OFFERS_SCANNED offers are looked at
+    pub(crate) fn internal_best_token_offer(&mut self, contract_and_token_id: &ContractAndTokenId, owner_id: &AccountId) -> Option<TokenOffer> {
+        let offer_book = self.token_offer_book.get(contract_and_token_id)?;
+
+        let mut expired_offer_ids = Vec::new();
+        let mut best_offer = None;
+        for (_, offer_id) in offer_book.iter_rev().take(MAX_OFFERS_SCANNED) {
+            let offer = self.token_offers.get(&offer_id).unwrap();
+            if offer.is_expired() {
+                expired_offer_ids.push(offer_id);
+            } else if offer.buyer_id != *owner_id {
+                best_offer = Some(offer);
+                break;
+            }
+        }
+
+        for offer_id in expired_offer_ids {
+            let offer = self.internal_remove_token_offer(offer_id);
+            pay_account(&near_token_id(), offer.buyer_id, offer.price.0);
+        }
+
+        best_offer
+    }
+
+    /*
+        internal method for selling a token to an offer when its owner calls nft_approve with an accept_offer msg.
+        With an offer ID, that token or collection offer is accepted. Without one, the best of the token's offers
+        and its collection's offers is. The offer is settled like a fixed price sale, with royalties and fees
+    */
+    pub(crate) fn internal_accept_offer(
+        &mut self,
+        owner_id: AccountId,
+        approval_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        offer_id: Option<u64>,
+        min_price: Option<U128>,
+    ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        //a fixed price listing is taken off the market since the token is sold through the offer. Auctions
+        //may be holding bids so they have to be ended or removed first
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            assert!(!sale.is_auction, "Token is being auctioned. End or remove the auction before accepting an offer");
+            self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        }
+
+        let (buyer_id, price) = match offer_id {
+            Some(offer_id) if self.token_offers.get(&offer_id).is_some() => {
+                let offer = self.internal_remove_token_offer(offer_id);
+                assert!(
+                    offer.nft_contract_id == nft_contract_id && offer.token_id == token_id,
+                    "Offer {} is not for this token",
+                    offer_id
+                );
+                assert!(!offer.is_expired(), "Offer {} has expired", offer_id);
+                (offer.buyer_id, offer.price)
+            }
+            Some(offer_id) => {
+                let offer = self.internal_remove_collection_offer(offer_id);
+                assert_eq!(offer.nft_contract_id, nft_contract_id, "Offer {} is not for this collection", offer_id);
+                assert!(!offer.is_expired(), "Offer {} has expired", offer_id);
+                (offer.buyer_id, offer.price)
+            }
+            None => {
+                //the owner's own offers are skipped rather than picked and rejected below
+                let token_offer = self.internal_best_token_offer(&contract_and_token_id, &owner_id);
+                let collection_offer = self.internal_best_collection_offer(&nft_contract_id, &owner_id);
+
+                //ties go to the offer on the token itself
+                match (token_offer, collection_offer) {
+                    (Some(token_offer), Some(collection_offer)) if collection_offer.price.0 > token_offer.price.0 => {
+                        self.internal_remove_collection_offer(collection_offer.offer_id);
+                        (collection_offer.buyer_id, collection_offer.price)
+                    }
+                    (Some(token_offer), _) => {
+                        self.internal_remove_token_offer(token_offer.offer_id);
+                        (token_offer.buyer_id, token_offer.price)
+                    }
+                    (None, Some(collection_offer)) => {
+                        self.internal_remove_collection_offer(collection_offer.offer_id);
+                        (collection_offer.buyer_id, collection_offer.price)
+                    }
+                    (None, None) => env::panic_str("No offers for this token"),
+                }
+            }
+        };
+
+        assert_ne!(buyer_id, owner_id, "Cannot accept your own offer");
+        if let Some(min_price) = min_price {
+            assert!(price.0 >= min_price.0, "Offer {} is below the minimum price {}", price.0, min_price.0);
+        }
+
+        //the offer is settled like a fixed price sale that is bought straight away
+        let sale = Sale::new_fixed_price(owner_id, approval_id, nft_contract_id, token_id, near_token_id(), price.0);
+        self.internal_transfer_and_pay(sale, near_token_id(), price, buyer_id, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::serde_json::{json, Value};
+
+    fn pay_storage(contract: &mut Contract, account_id: AccountId) {
+        call(account_id, ONE_NEAR, NOW);
+        contract.storage_deposit(None);
+    }
+
+    fn token_offer(contract: &mut Contract, buyer_id: AccountId, token_id: &str, price: u128, expires_at: Option<u64>) -> u64 {
+        pay_storage(contract, buyer_id.clone());
+        call(buyer_id, price, NOW);
+        contract.make_token_offer(nft(), token_id.to_string(), expires_at.map(U64))
+    }
+
+    fn collection_offer(contract: &mut Contract, buyer_id: AccountId, price: u128) -> u64 {
+        pay_storage(contract, buyer_id.clone());
+        call(buyer_id, price, NOW);
+        contract.make_collection_offer(nft(), None)
+    }
+
+    fn accept(contract: &mut Contract, accept_offer: Value) {
+        approve(contract, alice(), "1", json!({ "accept_offer": accept_offer }), NOW + 1);
+    }
+
+    #[test]
+    fn the_best_offer_of_the_token_and_its_collection_is_accepted() {
+        let mut contract = new_market();
+        token_offer(&mut contract, bob(), "1", 500, None);
+        collection_offer(&mut contract, carol(), 600);
+
+        accept(&mut contract, json!({}));
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(carol()));
+        assert_eq!(contract.get_token_offers(format!("{}{}1", nft(), DELIMETER), None, Some(10)).len(), 1);
+    }
+
+    #[test]
+    fn ties_go_to_the_offer_on_the_token() {
+        let mut contract = new_market();
+        collection_offer(&mut contract, carol(), 500);
+        token_offer(&mut contract, bob(), "1", 500, None);
+
+        accept(&mut contract, json!({}));
+        assert_eq!(scheduled_call("nft_transfer_payout")["receiver_id"], json!(bob()));
+    }
+
+    #[test]
+    fn a_chosen_offer_is_accepted_and_paid_like_a_sale() {
+        let mut contract = new_market();
+        token_offer(&mut contract, bob(), "1", 900, None);
+        let offer_id = token_offer(&mut contract, carol(), "1", 500, None);
+
+        accept(&mut contract, json!({ "offer_id": offer_id }));
+        assert_eq!(scheduled_call("resolve_purchase")["buyer_id"], json!(carol()));
+
+        resolve_purchase(&mut contract, Some(vec![(alice(), 500)]), NOW + 1);
+        let mut paid = payments();
+        paid.sort();
+        assert_eq!(paid, vec![("near".to_string(), alice(), 485)]);
+        assert_eq!(
+            fee_balances(&contract, &[account("charges.near"), account("commissions.near")]),
+            vec![
+                ("near".to_string(), account("charges.near"), 5),
+                ("near".to_string(), account("commissions.near"), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_transfers_refund_the_offer() {
+        let mut contract = new_market();
+        token_offer(&mut contract, bob(), "1", 500, None);
+
+        accept(&mut contract, json!({}));
+        resolve_purchase(&mut contract, None, NOW + 1);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 500)]);
+    }
+
+    #[test]
+    fn fixed_price_listings_are_taken_down_when_an_offer_is_accepted() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "1000" }, "is_auction": false }), NOW);
+        token_offer(&mut contract, bob(), "1", 500, None);
+
+        accept(&mut contract, json!({}));
+        assert!(sale(&contract, "1").is_none());
+    }
+
+    #[test]
+    fn expired_offers_are_refunded_while_looking_for_the_best_one() {
+        let mut contract = new_market();
+        token_offer(&mut contract, bob(), "1", 500, None);
+        token_offer(&mut contract, carol(), "1", 900, Some(NOW + 1));
+
+        accept(&mut contract, json!({}));
+        assert_eq!(payments(), vec![("near".to_string(), carol(), 900)]);
+        assert_eq!(scheduled_call("resolve_purchase")["buyer_id"], json!(bob()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Offer 0 is not for this token")]
+    fn offers_on_other_tokens_cannot_be_accepted() {
+        let mut contract = new_market();
+        token_offer(&mut contract, bob(), "2", 500, None);
+
+        accept(&mut contract, json!({ "offer_id": 0 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot make an offer on your own token")]
+    fn sellers_cannot_make_offers_on_their_own_listings() {
+        let mut contract = new_market();
+        list(&mut contract, alice(), "1", json!({ "sale_conditions": { "near": "1000" }, "is_auction": false }), NOW);
+
+        token_offer(&mut contract, alice(), "1", 500, None);
+    }
+
+    #[test]
+    fn cancelling_refunds_the_offer() {
+        let mut contract = new_market();
+        let offer_id = token_offer(&mut contract, bob(), "1", 500, None);
+
+        call(bob(), 1, NOW + 1);
+        contract.cancel_token_offer(offer_id);
+        assert_eq!(payments(), vec![("near".to_string(), bob(), 500)]);
+        assert!(contract.get_token_offers_by_buyer_id(bob()).is_empty());
+    }
+}